@@ -0,0 +1,21 @@
+-- Migration: Add accent- and case-insensitive city columns
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+-- unaccent() is only STABLE because its dictionary can change; pinning the
+-- dictionary makes it safe to use in generated columns and indexes.
+CREATE OR REPLACE FUNCTION immutable_unaccent(text)
+RETURNS text
+LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT
+AS $$ SELECT public.unaccent('public.unaccent'::regdictionary, $1) $$;
+
+ALTER TABLE customers
+    ADD COLUMN customer_city_normalized VARCHAR(100)
+    GENERATED ALWAYS AS (lower(immutable_unaccent(customer_city))) STORED;
+
+ALTER TABLE sellers
+    ADD COLUMN seller_city_normalized VARCHAR(100)
+    GENERATED ALWAYS AS (lower(immutable_unaccent(seller_city))) STORED;
+
+-- text_pattern_ops serves both equality and prefix (LIKE 'abc%') lookups.
+CREATE INDEX idx_customers_city_normalized ON customers(customer_city_normalized text_pattern_ops);
+CREATE INDEX idx_sellers_city_normalized ON sellers(seller_city_normalized text_pattern_ops);
@@ -0,0 +1,13 @@
+-- Migration: Add trigram indexes for fuzzy search
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX idx_customers_id_trgm ON customers USING GIN (customer_id gin_trgm_ops);
+CREATE INDEX idx_customers_unique_id_trgm ON customers USING GIN (customer_unique_id gin_trgm_ops);
+CREATE INDEX idx_customers_city_trgm ON customers USING GIN (customer_city_normalized gin_trgm_ops);
+CREATE INDEX idx_customers_zip_code_prefix_trgm ON customers USING GIN (customer_zip_code_prefix gin_trgm_ops);
+
+CREATE INDEX idx_sellers_id_trgm ON sellers USING GIN (seller_id gin_trgm_ops);
+CREATE INDEX idx_sellers_city_trgm ON sellers USING GIN (seller_city_normalized gin_trgm_ops);
+CREATE INDEX idx_sellers_zip_code_prefix_trgm ON sellers USING GIN (seller_zip_code_prefix gin_trgm_ops);
+
+CREATE INDEX idx_orders_id_trgm ON orders USING GIN (order_id gin_trgm_ops);
@@ -0,0 +1,12 @@
+-- Migration: Create zip code centroids table
+CREATE TABLE IF NOT EXISTS zip_code_centroids (
+    zip_code_prefix VARCHAR(10) PRIMARY KEY,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    city VARCHAR(100) NOT NULL,
+    state VARCHAR(2) NOT NULL,
+    sample_count INTEGER NOT NULL
+);
+
+-- Serves the bounding-box prefilter of proximity searches.
+CREATE INDEX idx_zip_code_centroids_lat_lng ON zip_code_centroids(latitude, longitude);
@@ -0,0 +1,16 @@
+-- Migration: Create order payments table
+CREATE TABLE IF NOT EXISTS order_payments (
+    order_id VARCHAR(32) NOT NULL,
+    payment_sequential INTEGER NOT NULL,
+    payment_type VARCHAR(20) NOT NULL,
+    payment_installments INTEGER NOT NULL,
+    payment_value DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (order_id, payment_sequential),
+    CONSTRAINT fk_order_payments_order
+        FOREIGN KEY (order_id)
+        REFERENCES orders(order_id)
+        ON DELETE CASCADE
+        ON UPDATE NO ACTION
+);
+
+CREATE INDEX idx_order_payments_payment_type ON order_payments(payment_type);
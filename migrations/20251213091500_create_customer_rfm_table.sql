@@ -0,0 +1,17 @@
+-- Migration: Create customer RFM table
+-- Recency/frequency/monetary scores per person (customer_unique_id),
+-- rebuilt by the RFM segmentation job.
+CREATE TABLE IF NOT EXISTS customer_rfm (
+    customer_unique_id VARCHAR(32) PRIMARY KEY,
+    last_purchase_at TIMESTAMP NOT NULL,
+    recency_days INTEGER NOT NULL,
+    frequency INTEGER NOT NULL,
+    monetary DOUBLE PRECISION NOT NULL,
+    r_score SMALLINT NOT NULL,
+    f_score SMALLINT NOT NULL,
+    m_score SMALLINT NOT NULL,
+    segment VARCHAR(30) NOT NULL,
+    computed_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_customer_rfm_segment ON customer_rfm(segment);
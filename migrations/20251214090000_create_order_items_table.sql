@@ -0,0 +1,24 @@
+-- Migration: Create order items table
+CREATE TABLE IF NOT EXISTS order_items (
+    order_id VARCHAR(32) NOT NULL,
+    order_item_id INTEGER NOT NULL,
+    product_id VARCHAR(32) NOT NULL,
+    seller_id VARCHAR(32) NOT NULL,
+    shipping_limit_date TIMESTAMP NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    freight_value DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (order_id, order_item_id),
+    CONSTRAINT fk_order_items_order
+        FOREIGN KEY (order_id)
+        REFERENCES orders(order_id)
+        ON DELETE CASCADE
+        ON UPDATE NO ACTION,
+    CONSTRAINT fk_order_items_seller
+        FOREIGN KEY (seller_id)
+        REFERENCES sellers(seller_id)
+        ON DELETE CASCADE
+        ON UPDATE NO ACTION
+);
+
+CREATE INDEX idx_order_items_seller_id ON order_items(seller_id);
+CREATE INDEX idx_order_items_product_id ON order_items(product_id);
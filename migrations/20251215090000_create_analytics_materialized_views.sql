@@ -0,0 +1,62 @@
+-- Migration: Create analytics materialized views
+-- Per-order facts behind the /analytics endpoints, refreshed concurrently by
+-- the analytics refresh job. Concurrent refreshes need a unique index.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_order_facts AS
+SELECT
+    o.order_id,
+    o.customer_id,
+    c.customer_unique_id,
+    c.customer_state,
+    o.order_status,
+    o.order_purchase_timestamp,
+    o.order_approved_at,
+    o.order_delivered_carrier_date,
+    o.order_delivered_customer_date,
+    o.order_estimated_delivery_date,
+    EXTRACT(EPOCH FROM o.order_approved_at - o.order_purchase_timestamp)::float8 / 86400.0
+        AS approval_days,
+    EXTRACT(EPOCH FROM o.order_delivered_carrier_date - o.order_approved_at)::float8 / 86400.0
+        AS handoff_days,
+    EXTRACT(EPOCH FROM o.order_delivered_customer_date - o.order_delivered_carrier_date)::float8 / 86400.0
+        AS carrier_to_customer_days,
+    EXTRACT(EPOCH FROM o.order_delivered_customer_date - o.order_purchase_timestamp)::float8 / 86400.0
+        AS delivery_days,
+    EXTRACT(EPOCH FROM o.order_delivered_customer_date - o.order_estimated_delivery_date)::float8 / 86400.0
+        AS days_late
+FROM orders o
+JOIN customers c ON c.customer_id = o.customer_id;
+
+CREATE UNIQUE INDEX idx_mv_order_facts_order_id ON mv_order_facts(order_id);
+CREATE INDEX idx_mv_order_facts_purchase_timestamp ON mv_order_facts(order_purchase_timestamp);
+CREATE INDEX idx_mv_order_facts_customer_state ON mv_order_facts(customer_state);
+
+-- One row per order and seller state, so that orders with several items from
+-- the same state count once.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_order_shipments AS
+SELECT DISTINCT
+    f.order_id,
+    s.seller_state,
+    f.customer_state,
+    f.order_purchase_timestamp,
+    f.delivery_days
+FROM order_items i
+JOIN mv_order_facts f ON f.order_id = i.order_id
+JOIN sellers s ON s.seller_id = i.seller_id;
+
+CREATE UNIQUE INDEX idx_mv_order_shipments_order_seller_state
+    ON mv_order_shipments(order_id, seller_state);
+CREATE INDEX idx_mv_order_shipments_purchase_timestamp
+    ON mv_order_shipments(order_purchase_timestamp);
+
+-- Last successful refresh of each analytics dataset.
+CREATE TABLE IF NOT EXISTS analytics_refresh_log (
+    dataset VARCHAR(64) PRIMARY KEY,
+    last_refreshed_at TIMESTAMP NOT NULL,
+    duration_ms BIGINT NOT NULL
+);
+
+INSERT INTO analytics_refresh_log (dataset, last_refreshed_at, duration_ms)
+VALUES
+    ('mv_order_facts', now() AT TIME ZONE 'UTC', 0),
+    ('mv_order_shipments', now() AT TIME ZONE 'UTC', 0)
+ON CONFLICT (dataset) DO NOTHING;
@@ -0,0 +1,14 @@
+-- Migration: Create products table
+CREATE TABLE IF NOT EXISTS products (
+    product_id VARCHAR(32) PRIMARY KEY,
+    product_category_name VARCHAR(64),
+    product_name_length INTEGER,
+    product_description_length INTEGER,
+    product_photos_qty INTEGER,
+    product_weight_g INTEGER,
+    product_length_cm INTEGER,
+    product_height_cm INTEGER,
+    product_width_cm INTEGER
+);
+
+CREATE INDEX idx_products_category_name ON products(product_category_name);
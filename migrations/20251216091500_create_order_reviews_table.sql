@@ -0,0 +1,20 @@
+-- Migration: Create order reviews table
+-- The same review_id can cover several orders, hence the composite key.
+CREATE TABLE IF NOT EXISTS order_reviews (
+    review_id VARCHAR(32) NOT NULL,
+    order_id VARCHAR(32) NOT NULL,
+    review_score SMALLINT NOT NULL CHECK (review_score BETWEEN 1 AND 5),
+    review_comment_title TEXT,
+    review_comment_message TEXT,
+    review_creation_date TIMESTAMP NOT NULL,
+    review_answer_timestamp TIMESTAMP,
+    PRIMARY KEY (review_id, order_id),
+    CONSTRAINT fk_order_reviews_order
+        FOREIGN KEY (order_id)
+        REFERENCES orders(order_id)
+        ON DELETE CASCADE
+        ON UPDATE NO ACTION
+);
+
+CREATE INDEX idx_order_reviews_order_id ON order_reviews(order_id);
+CREATE INDEX idx_order_reviews_creation_date ON order_reviews(review_creation_date);
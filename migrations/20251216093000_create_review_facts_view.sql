@@ -0,0 +1,23 @@
+-- Migration: Create review facts materialized view
+-- One row per review and order, with the lateness of the order it rates.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_review_facts AS
+SELECT
+    r.review_id,
+    r.order_id,
+    r.review_score,
+    r.review_creation_date,
+    f.customer_state,
+    f.order_purchase_timestamp,
+    f.order_delivered_customer_date,
+    f.days_late
+FROM order_reviews r
+JOIN mv_order_facts f ON f.order_id = r.order_id;
+
+CREATE UNIQUE INDEX idx_mv_review_facts_review_order
+    ON mv_review_facts(review_id, order_id);
+CREATE INDEX idx_mv_review_facts_purchase_timestamp
+    ON mv_review_facts(order_purchase_timestamp);
+
+INSERT INTO analytics_refresh_log (dataset, last_refreshed_at, duration_ms)
+VALUES ('mv_review_facts', now() AT TIME ZONE 'UTC', 0)
+ON CONFLICT (dataset) DO NOTHING;
@@ -0,0 +1,22 @@
+-- Migration: Create payment facts materialized view
+-- One row per payment, with the state and purchase time of its order.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_payment_facts AS
+SELECT
+    p.order_id,
+    p.payment_sequential,
+    p.payment_type,
+    p.payment_installments,
+    p.payment_value,
+    f.customer_state,
+    f.order_purchase_timestamp
+FROM order_payments p
+JOIN mv_order_facts f ON f.order_id = p.order_id;
+
+CREATE UNIQUE INDEX idx_mv_payment_facts_order_sequential
+    ON mv_payment_facts(order_id, payment_sequential);
+CREATE INDEX idx_mv_payment_facts_purchase_timestamp
+    ON mv_payment_facts(order_purchase_timestamp);
+
+INSERT INTO analytics_refresh_log (dataset, last_refreshed_at, duration_ms)
+VALUES ('mv_payment_facts', now() AT TIME ZONE 'UTC', 0)
+ON CONFLICT (dataset) DO NOTHING;
@@ -0,0 +1,107 @@
+-- Migration: Create delivery estimate materialized views
+-- Historical durations behind the delivery date estimator, at decreasing
+-- levels of detail so that thin routes can fall back to broader ones.
+
+-- Carrier handoff to customer delivery, in days. `seller_state` is '' and
+-- `purchase_month` 0 on levels that do not group by them.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_transit_times AS
+WITH deliveries AS (
+    SELECT
+        order_id,
+        customer_state,
+        EXTRACT(MONTH FROM order_purchase_timestamp)::int AS purchase_month,
+        carrier_to_customer_days AS days
+    FROM mv_order_facts
+    WHERE carrier_to_customer_days >= 0
+),
+routes AS (
+    SELECT DISTINCT d.*, s.seller_state
+    FROM deliveries d
+    JOIN order_items i ON i.order_id = d.order_id
+    JOIN sellers s ON s.seller_id = i.seller_id
+)
+SELECT
+    'route_month' AS level, seller_state, customer_state, purchase_month,
+    COUNT(*) AS samples,
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days) AS p10_days,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days) AS p50_days,
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days) AS p90_days
+FROM routes
+GROUP BY seller_state, customer_state, purchase_month
+UNION ALL
+SELECT
+    'route', seller_state, customer_state, 0,
+    COUNT(*),
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days)
+FROM routes
+GROUP BY seller_state, customer_state
+UNION ALL
+SELECT
+    'destination_month', '', customer_state, purchase_month,
+    COUNT(*),
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days)
+FROM deliveries
+GROUP BY customer_state, purchase_month
+UNION ALL
+SELECT
+    'destination', '', customer_state, 0,
+    COUNT(*),
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days)
+FROM deliveries
+GROUP BY customer_state
+UNION ALL
+SELECT
+    'all', '', '', 0,
+    COUNT(*),
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days)
+FROM deliveries
+HAVING COUNT(*) > 0;
+
+CREATE UNIQUE INDEX idx_mv_transit_times_key
+    ON mv_transit_times(level, seller_state, customer_state, purchase_month);
+
+-- Purchase to carrier handoff, in days, per seller. The row with an empty
+-- `seller_id` covers all orders.
+CREATE MATERIALIZED VIEW IF NOT EXISTS mv_seller_handling AS
+WITH handoffs AS (
+    SELECT
+        order_id,
+        EXTRACT(EPOCH FROM order_delivered_carrier_date - order_purchase_timestamp)::float8
+            / 86400.0 AS days
+    FROM mv_order_facts
+    WHERE order_delivered_carrier_date >= order_purchase_timestamp
+)
+SELECT
+    i.seller_id,
+    COUNT(*) AS samples,
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY h.days) AS p10_days,
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY h.days) AS p50_days,
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY h.days) AS p90_days
+FROM handoffs h
+JOIN (SELECT DISTINCT order_id, seller_id FROM order_items) i ON i.order_id = h.order_id
+GROUP BY i.seller_id
+UNION ALL
+SELECT
+    '',
+    COUNT(*),
+    percentile_cont(0.1) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.5) WITHIN GROUP (ORDER BY days),
+    percentile_cont(0.9) WITHIN GROUP (ORDER BY days)
+FROM handoffs
+HAVING COUNT(*) > 0;
+
+CREATE UNIQUE INDEX idx_mv_seller_handling_seller_id ON mv_seller_handling(seller_id);
+
+INSERT INTO analytics_refresh_log (dataset, last_refreshed_at, duration_ms)
+VALUES
+    ('mv_transit_times', now() AT TIME ZONE 'UTC', 0),
+    ('mv_seller_handling', now() AT TIME ZONE 'UTC', 0)
+ON CONFLICT (dataset) DO NOTHING;
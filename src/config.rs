@@ -1,4 +1,7 @@
 use crate::error::AppError;
+use crate::routes::{DEPRECATION, SUNSET};
+use axum::http::header;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use std::env;
 use std::time::Duration;
 use tower_http::cors::{AllowOrigin, Any, CorsLayer};
@@ -7,8 +10,17 @@ pub struct AppConfig {
     pub database_url: String,
     pub port: u16,
     pub cors: CorsConfig,
+    /// `None` disables the scheduled analytics refresh.
+    pub analytics_refresh_interval: Option<Duration>,
+    /// Announced in the `Deprecation` header of the unversioned aliases.
+    pub unversioned_deprecated_since: DateTime<Utc>,
+    /// Announced in the `Sunset` header of the unversioned aliases.
+    pub unversioned_sunset: Option<DateTime<Utc>>,
 }
 
+/// Day the unversioned paths were deprecated in favour of `/v1`.
+const DEFAULT_UNVERSIONED_DEPRECATED_SINCE: &str = "2026-10-18";
+
 pub fn load_config() -> Result<AppConfig, AppError> {
     let database_url = env::var("DATABASE_URL")
         .map_err(|_| AppError::ConfigError("DATABASE_URL must be set".to_string()))?;
@@ -20,13 +32,51 @@ pub fn load_config() -> Result<AppConfig, AppError> {
 
     let cors = load_cors_config()?;
 
+    let analytics_refresh_secs: u64 = env::var("ANALYTICS_REFRESH_INTERVAL_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .map_err(|e| {
+            AppError::ConfigError(format!("Invalid ANALYTICS_REFRESH_INTERVAL_SECS: {}", e))
+        })?;
+    let analytics_refresh_interval =
+        (analytics_refresh_secs > 0).then(|| Duration::from_secs(analytics_refresh_secs));
+
+    let unversioned_deprecated_since = match date_var("UNVERSIONED_DEPRECATED_SINCE")? {
+        Some(since) => since,
+        None => parse_date(
+            "UNVERSIONED_DEPRECATED_SINCE",
+            DEFAULT_UNVERSIONED_DEPRECATED_SINCE,
+        )?,
+    };
+    let unversioned_sunset = date_var("UNVERSIONED_SUNSET")?;
+
     Ok(AppConfig {
         database_url,
         port,
         cors,
+        analytics_refresh_interval,
+        unversioned_deprecated_since,
+        unversioned_sunset,
     })
 }
 
+/// Reads a `YYYY-MM-DD` variable as midnight UTC; `None` when unset or empty.
+fn date_var(name: &str) -> Result<Option<DateTime<Utc>>, AppError> {
+    env::var(name)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| parse_date(name, &v))
+        .transpose()
+}
+
+fn parse_date(name: &str, value: &str) -> Result<DateTime<Utc>, AppError> {
+    value
+        .trim()
+        .parse::<NaiveDate>()
+        .map_err(|e| AppError::ConfigError(format!("Invalid {}: {}", name, e)))
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+}
+
 pub struct CorsConfig {
     pub allowed_origins: AllowOrigin,
     pub allow_credentials: bool,
@@ -63,6 +113,8 @@ pub fn create_cors_layer(config: CorsConfig) -> CorsLayer {
         .allow_origin(config.allowed_origins)
         .allow_methods(Any)
         .allow_headers(Any)
+        // Lets browser clients see that an unversioned path is deprecated.
+        .expose_headers([DEPRECATION, SUNSET, header::LINK])
         .allow_credentials(config.allow_credentials)
         .max_age(Duration::from_secs(config.max_age_seconds))
 }
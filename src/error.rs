@@ -1,21 +1,227 @@
 use axum::{
-    http::StatusCode,
-    response::{IntoResponse, Json, Response},
+    body::Body,
+    extract::Request,
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
 };
+use serde::Serialize;
 use sqlx::migrate::MigrateError;
 use tracing::error;
+use utoipa::ToSchema;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
 pub type AppResult<T> = Result<T, AppError>;
 
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// RFC 7807 body of every error response. `code` identifies the kind of
+/// error and is stable across releases; `type` is the same code as a URI.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    #[schema(example = "urn:problem:validation_failed")]
+    pub kind: String,
+    #[schema(example = "Validation failed")]
+    pub title: &'static str,
+    #[schema(example = 400)]
+    pub status: u16,
+    #[schema(example = "The request failed validation")]
+    pub detail: String,
+    /// Path of the request that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "/v1/customers")]
+    pub instance: Option<String>,
+    #[schema(example = "validation_failed")]
+    pub code: &'static str,
+    /// Failing fields; only present on validation errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+}
+
+/// A constraint a field did not satisfy.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Field name; nested fields are joined with `.`, list items as `[i]`.
+    #[schema(example = "customer_state")]
+    pub field: String,
+    /// Name of the constraint, e.g. `length` or `range`.
+    #[schema(example = "length")]
+    pub code: String,
+    #[schema(example = "length must be 2")]
+    pub message: String,
+    /// JSON Pointer (RFC 6901) to the field within the request body or
+    /// query string.
+    #[schema(example = "/customer_state")]
+    pub pointer: String,
+}
+
+impl FieldError {
+    pub fn new(field: String, code: impl Into<String>, message: String) -> Self {
+        Self {
+            pointer: json_pointer(&field),
+            field,
+            code: code.into(),
+            message,
+        }
+    }
+}
+
+/// Converts a field path such as `items[0].price` to `/items/0/price`.
+fn json_pointer(field: &str) -> String {
+    field
+        .split(['.', '['])
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let token = token.strip_suffix(']').unwrap_or(token);
+            format!("/{}", token.replace('~', "~0").replace('/', "~1"))
+        })
+        .collect()
+}
+
+impl ProblemDetails {
+    pub fn new(
+        status: StatusCode,
+        code: &'static str,
+        title: &'static str,
+        detail: String,
+    ) -> Self {
+        Self {
+            kind: format!("urn:problem:{}", code),
+            title,
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            code,
+            errors: None,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("problem details are always serializable")
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            self.body(),
+        )
+            .into_response();
+        // Kept so that `problem_instance` can fill in the request path.
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Sets `instance` on problem responses to the path of the request.
+pub async fn problem_instance(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+
+    let Some(mut problem) = response.extensions().get::<ProblemDetails>().cloned() else {
+        return response;
+    };
+    problem.instance = Some(path);
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+    );
+    let body = problem.body();
+    parts.extensions.insert(problem);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Lists every failing field of `errors`, sorted by field name.
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = Vec::new();
+    collect_field_errors(errors, "", &mut fields);
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => out.extend(errors.iter().map(|e| {
+                FieldError::new(
+                    path.clone(),
+                    e.code.to_string(),
+                    e.message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| describe_constraint(e)),
+                )
+            })),
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(errors, &format!("{}[{}]", path, index), out);
+                }
+            }
+        }
+    }
+}
+
+/// Default message for a constraint declared without one.
+fn describe_constraint(error: &validator::ValidationError) -> String {
+    let subject = match error.code.as_ref() {
+        "length" => "length",
+        "range" => "value",
+        _ => return format!("failed the '{}' constraint", error.code),
+    };
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    match (param("min"), param("max"), param("equal")) {
+        (_, _, Some(equal)) => format!("{} must be {}", subject, equal),
+        (Some(min), Some(max), _) if min == max => format!("{} must be {}", subject, min),
+        (Some(min), Some(max), _) => format!("{} must be between {} and {}", subject, min, max),
+        (Some(min), None, _) => format!("{} must be at least {}", subject, min),
+        (None, Some(max), _) => format!("{} must be at most {}", subject, max),
+        (None, None, _) => format!("{} is out of bounds", subject),
+    }
+}
+
 #[derive(Debug)]
 pub enum AppError {
     DatabaseError(sqlx::Error),
     MigrationError(MigrateError),
     NotFound,
+    MethodNotAllowed,
     ConfigError(String),
     ValidationError(validator::ValidationErrors),
     NoChangesToUpdate,
     AlreadyExists(String),
+    BadRequest(String),
+    InternalError(String),
+}
+
+impl AppError {
+    /// Machine-readable code reported as the problem's `code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::DatabaseError(_) => "database_error",
+            AppError::MigrationError(_) => "migration_error",
+            AppError::NotFound => "not_found",
+            AppError::MethodNotAllowed => "method_not_allowed",
+            AppError::ConfigError(_) => "config_error",
+            AppError::ValidationError(_) => "validation_failed",
+            AppError::NoChangesToUpdate => "no_changes",
+            AppError::AlreadyExists(_) => "already_exists",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InternalError(_) => "internal_error",
+        }
+    }
 }
 
 impl From<sqlx::Error> for AppError {
@@ -38,20 +244,35 @@ impl From<validator::ValidationErrors> for AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, msg) = match &self {
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Resource Not Found".to_string()),
-            AppError::ValidationError(e) => {
-                (StatusCode::BAD_REQUEST, format!("Validation error: {}", e))
-            }
+        let code = self.code();
+        let (status, title, detail) = match &self {
+            AppError::NotFound => (
+                StatusCode::NOT_FOUND,
+                "Resource not found",
+                "Resource Not Found".to_string(),
+            ),
+            AppError::MethodNotAllowed => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Method not allowed",
+                "Method Not Allowed".to_string(),
+            ),
+            AppError::ValidationError(_) => (
+                StatusCode::BAD_REQUEST,
+                "Validation failed",
+                "The request failed validation".to_string(),
+            ),
             AppError::NoChangesToUpdate => (
                 StatusCode::BAD_REQUEST,
+                "No changes to update",
                 "No valid fields provided for update.".to_string(),
             ),
-            AppError::AlreadyExists(msg) => (StatusCode::CONFLICT, msg.clone()),
+            AppError::AlreadyExists(msg) => (StatusCode::CONFLICT, "Already exists", msg.clone()),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "Bad request", msg.clone()),
             AppError::DatabaseError(e) => {
                 error!("Database Error: {:?}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
                     "Database Operation Failed".to_string(),
                 )
             }
@@ -59,6 +280,7 @@ impl IntoResponse for AppError {
                 error!("Migration Error: {:?}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    "Migration error",
                     "Database Migration Failed".to_string(),
                 )
             }
@@ -66,11 +288,24 @@ impl IntoResponse for AppError {
                 error!("Configuration Error: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    "Configuration error",
                     format!("Configuration Error: {}", e),
                 )
             }
+            AppError::InternalError(e) => {
+                error!("Internal Error: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error",
+                    "Internal Server Error".to_string(),
+                )
+            }
         };
 
-        (status, Json(serde_json::json!({"error": msg}))).into_response()
+        let mut problem = ProblemDetails::new(status, code, title, detail);
+        if let AppError::ValidationError(errors) = &self {
+            problem.errors = Some(field_errors(errors));
+        }
+        problem.into_response()
     }
 }
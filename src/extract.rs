@@ -0,0 +1,181 @@
+use std::error::Error as StdError;
+use std::iter;
+
+use axum::{
+    extract::{
+        FromRequest, FromRequestParts, Request,
+        path::ErrorKind,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::error;
+
+use crate::error::{FieldError, ProblemDetails};
+
+/// JSON request body, rejected with a problem naming the offending field.
+/// Also serializes response bodies, like `axum::Json`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+/// Query string parameters, rejected with a problem naming the offending
+/// parameter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+/// Path parameters, rejected with a problem naming the offending parameter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ProblemDetails;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Json::<T>::from_request(req, state).await {
+            Ok(axum::Json(value)) => Ok(Json(value)),
+            Err(rejection) => Err(json_problem(rejection)),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ProblemDetails;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Query(value)),
+            Err(rejection) => Err(query_problem(rejection)),
+        }
+    }
+}
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ProblemDetails;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(rejection) => Err(path_problem(rejection)),
+        }
+    }
+}
+
+fn json_problem(rejection: JsonRejection) -> ProblemDetails {
+    let field = serde_field_error::<serde_json::Error>(&rejection);
+    rejection_problem(rejection.status(), rejection.body_text(), field)
+}
+
+fn query_problem(rejection: QueryRejection) -> ProblemDetails {
+    let field = serde_field_error::<serde::de::value::Error>(&rejection);
+    rejection_problem(rejection.status(), rejection.body_text(), field)
+}
+
+fn path_problem(rejection: PathRejection) -> ProblemDetails {
+    let field = match &rejection {
+        PathRejection::FailedToDeserializePathParams(e) => match e.kind() {
+            ErrorKind::ParseErrorAtKey { key, .. }
+            | ErrorKind::InvalidUtf8InPathParam { key }
+            | ErrorKind::DeserializeError { key, .. } => Some(FieldError::new(
+                key.clone(),
+                "invalid",
+                e.kind().to_string(),
+            )),
+            _ => None,
+        },
+        _ => None,
+    };
+    rejection_problem(rejection.status(), rejection.body_text(), field)
+}
+
+/// Field named by the `serde_path_to_error` error the extractors wrap their
+/// deserialization failures in.
+fn serde_field_error<E>(rejection: &(dyn StdError + 'static)) -> Option<FieldError>
+where
+    E: StdError + 'static,
+{
+    let error = iter::successors(Some(rejection), |&e| e.source())
+        .find_map(|e| e.downcast_ref::<serde_path_to_error::Error<E>>())?;
+    let path = error.path();
+    let parent = if path.iter().next().is_none() {
+        String::new()
+    } else {
+        path.to_string()
+    };
+    let message = error.inner().to_string();
+
+    // Missing fields are reported against the struct that lacks them.
+    if let Some(name) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(name, _)| name)
+    {
+        let field = if parent.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", parent, name)
+        };
+        return Some(FieldError::new(field, "required", message));
+    }
+
+    if parent.is_empty() {
+        return None;
+    }
+    Some(FieldError::new(parent, "invalid", message))
+}
+
+/// Maps an extractor rejection to a problem. Malformed input is reported as
+/// `bad_request`, whatever status axum would have used.
+fn rejection_problem(
+    status: StatusCode,
+    detail: String,
+    field: Option<FieldError>,
+) -> ProblemDetails {
+    let mut problem = match status {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => ProblemDetails::new(
+            status,
+            "unsupported_media_type",
+            "Unsupported media type",
+            detail,
+        ),
+        StatusCode::PAYLOAD_TOO_LARGE => {
+            ProblemDetails::new(status, "payload_too_large", "Payload too large", detail)
+        }
+        status if status.is_server_error() => {
+            error!("Extractor Error: {}", detail);
+            ProblemDetails::new(
+                status,
+                "internal_error",
+                "Internal server error",
+                "Internal Server Error".to_string(),
+            )
+        }
+        _ => ProblemDetails::new(
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            "Bad request",
+            detail,
+        ),
+    };
+    problem.errors = field.map(|field| vec![field]);
+    problem
+}
@@ -0,0 +1,649 @@
+//! `?filter=` expressions for the listing endpoints.
+//!
+//! The syntax follows RSQL/FIQL: comparisons are `field<op>value`, `;` joins
+//! them with AND, `,` with OR (AND binds tighter) and parentheses group.
+//!
+//! ```text
+//! state==SP;city=in=(campinas,santos);order_purchase_timestamp=ge=2018-01-01
+//! ```
+//!
+//! Operators are `==`, `!=`, `=lt=`, `=le=`, `=gt=`, `=ge=`, `=in=`, `=out=`
+//! and `=null=` (`true`/`false`). `==` and `!=` on text fields treat `*` as a
+//! wildcard. Values containing reserved characters can be quoted with `'` or
+//! `"`. Fields are resolved against the resource's metadata, so only known
+//! columns ever reach the SQL.
+
+use crate::error::{AppError, AppResult};
+use crate::models::{FieldKind, FieldMeta, FilterMatch, Resource, SqlValue};
+
+const MAX_FILTER_LENGTH: usize = 2000;
+const MAX_FILTER_DEPTH: usize = 8;
+const MAX_LIST_VALUES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Out,
+    Null,
+}
+
+impl FilterOp {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "lt" => FilterOp::Lt,
+            "le" => FilterOp::Le,
+            "gt" => FilterOp::Gt,
+            "ge" => FilterOp::Ge,
+            "in" => FilterOp::In,
+            "out" => FilterOp::Out,
+            "null" => FilterOp::Null,
+            _ => return None,
+        })
+    }
+
+    fn takes_list(self) -> bool {
+        matches!(self, FilterOp::In | FilterOp::Out)
+    }
+}
+
+/// A comparison whose field and values have been checked against a resource.
+/// `matching` is set for fields not compared by exact value.
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Compare {
+        field: &'static FieldMeta,
+        matching: Option<FilterMatch>,
+        op: FilterOp,
+        value: SqlValue,
+    },
+    /// `==`/`!=` on a text field with `*` wildcards, as a LIKE pattern.
+    Like {
+        field: &'static FieldMeta,
+        matching: Option<FilterMatch>,
+        negated: bool,
+        pattern: String,
+    },
+    List {
+        field: &'static FieldMeta,
+        matching: Option<FilterMatch>,
+        negated: bool,
+        values: Vec<SqlValue>,
+    },
+    Null {
+        field: &'static FieldMeta,
+        is_null: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Predicate(Predicate),
+}
+
+impl FilterExpr {
+    /// Parses and validates `raw` against the filterable fields of `T`.
+    /// Blank input yields `None`.
+    pub fn parse<T: Resource>(raw: Option<&str>) -> AppResult<Option<Self>> {
+        let Some(raw) = raw.map(str::trim).filter(|r| !r.is_empty()) else {
+            return Ok(None);
+        };
+        if raw.len() > MAX_FILTER_LENGTH {
+            return Err(invalid(format!(
+                "expression exceeds {} characters",
+                MAX_FILTER_LENGTH
+            )));
+        }
+
+        let mut parser = Parser {
+            input: raw,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.pos < raw.len() {
+            return Err(parser.error("unexpected input"));
+        }
+
+        expr.resolve::<T>().map(Some)
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> AppError {
+    AppError::BadRequest(format!("Invalid filter: {}", message))
+}
+
+/// Syntax tree before field names and values are checked.
+enum RawExpr {
+    And(Vec<RawExpr>),
+    Or(Vec<RawExpr>),
+    Comparison {
+        field: String,
+        op: FilterOp,
+        values: Vec<String>,
+        list: bool,
+    },
+}
+
+impl RawExpr {
+    fn resolve<T: Resource>(self) -> AppResult<FilterExpr> {
+        match self {
+            RawExpr::And(items) => items
+                .into_iter()
+                .map(RawExpr::resolve::<T>)
+                .collect::<AppResult<_>>()
+                .map(FilterExpr::And),
+            RawExpr::Or(items) => items
+                .into_iter()
+                .map(RawExpr::resolve::<T>)
+                .collect::<AppResult<_>>()
+                .map(FilterExpr::Or),
+            RawExpr::Comparison {
+                field,
+                op,
+                values,
+                list,
+            } => resolve_comparison::<T>(&field, op, values, list).map(FilterExpr::Predicate),
+        }
+    }
+}
+
+fn resolve_comparison<T: Resource>(
+    name: &str,
+    op: FilterOp,
+    mut values: Vec<String>,
+    list: bool,
+) -> AppResult<Predicate> {
+    let field =
+        T::filter_field(name).ok_or_else(|| invalid(format!("unknown field '{}'", name)))?;
+    let matching = T::filter_match(field.name);
+
+    if op.takes_list() {
+        if values.len() > MAX_LIST_VALUES {
+            return Err(invalid(format!(
+                "'{}' accepts at most {} values",
+                name, MAX_LIST_VALUES
+            )));
+        }
+        let values = values
+            .iter()
+            .map(|v| parse_value(field, v))
+            .collect::<AppResult<_>>()?;
+        return Ok(Predicate::List {
+            field,
+            matching,
+            negated: op == FilterOp::Out,
+            values,
+        });
+    }
+
+    if list || values.len() != 1 {
+        return Err(invalid(format!(
+            "operator on '{}' expects a single value",
+            name
+        )));
+    }
+    let raw = values.remove(0);
+
+    match op {
+        FilterOp::Null => match raw.as_str() {
+            "true" => Ok(Predicate::Null {
+                field,
+                is_null: true,
+            }),
+            "false" => Ok(Predicate::Null {
+                field,
+                is_null: false,
+            }),
+            _ => Err(invalid(format!(
+                "=null= on '{}' expects true or false",
+                name
+            ))),
+        },
+        FilterOp::Eq | FilterOp::Ne if field.kind == FieldKind::Text && raw.contains('*') => {
+            Ok(Predicate::Like {
+                field,
+                matching,
+                negated: op == FilterOp::Ne,
+                pattern: like_pattern(&raw),
+            })
+        }
+        _ => Ok(Predicate::Compare {
+            field,
+            matching,
+            op,
+            value: parse_value(field, &raw)?,
+        }),
+    }
+}
+
+/// Timestamps accept a full `YYYY-MM-DDTHH:MM:SS` or a bare date, read as
+/// midnight.
+fn parse_value(field: &FieldMeta, raw: &str) -> AppResult<SqlValue> {
+    let value = match field.kind {
+        FieldKind::Text => Some(SqlValue::Text(raw.to_string())),
+        FieldKind::Timestamp => raw
+            .parse::<chrono::NaiveDateTime>()
+            .ok()
+            .or_else(|| chrono::NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S").ok())
+            .or_else(|| {
+                raw.parse::<chrono::NaiveDate>()
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+            })
+            .map(SqlValue::Timestamp),
+    };
+
+    value.ok_or_else(|| {
+        invalid(format!(
+            "'{}' is not a valid value for '{}'",
+            raw, field.name
+        ))
+    })
+}
+
+fn like_pattern(raw: &str) -> String {
+    raw.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> AppError {
+        invalid(format!("{} at position {}", message, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> AppResult<RawExpr> {
+        let mut items = vec![self.parse_and()?];
+        while self.eat(',') {
+            items.push(self.parse_and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            RawExpr::Or(items)
+        })
+    }
+
+    fn parse_and(&mut self) -> AppResult<RawExpr> {
+        let mut items = vec![self.parse_term()?];
+        while self.eat(';') {
+            items.push(self.parse_term()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            RawExpr::And(items)
+        })
+    }
+
+    fn parse_term(&mut self) -> AppResult<RawExpr> {
+        if self.eat('(') {
+            self.depth += 1;
+            if self.depth > MAX_FILTER_DEPTH {
+                return Err(self.error("expression is nested too deeply"));
+            }
+            let expr = self.parse_or()?;
+            if !self.eat(')') {
+                return Err(self.error("expected ')'"));
+            }
+            self.depth -= 1;
+            return Ok(expr);
+        }
+
+        let field = self.parse_field()?;
+        let op = self.parse_op()?;
+        let (values, list) = if self.eat('(') {
+            let mut values = vec![self.parse_value()?];
+            while self.eat(',') {
+                values.push(self.parse_value()?);
+            }
+            if !self.eat(')') {
+                return Err(self.error("expected ')'"));
+            }
+            (values, true)
+        } else {
+            (vec![self.parse_value()?], false)
+        };
+
+        Ok(RawExpr::Comparison {
+            field,
+            op,
+            values,
+            list,
+        })
+    }
+
+    fn parse_field(&mut self) -> AppResult<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            self.pos += c.len_utf8();
+        }
+        if start == self.pos {
+            return Err(self.error("expected a field name"));
+        }
+        Ok(self.input[start..self.pos].to_string())
+    }
+
+    fn parse_op(&mut self) -> AppResult<FilterOp> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        if let Some(stripped) = rest.strip_prefix("==") {
+            self.pos = self.input.len() - stripped.len();
+            return Ok(FilterOp::Eq);
+        }
+        if let Some(stripped) = rest.strip_prefix("!=") {
+            self.pos = self.input.len() - stripped.len();
+            return Ok(FilterOp::Ne);
+        }
+        if let Some(stripped) = rest.strip_prefix('=')
+            && let Some(end) = stripped.find('=')
+            && let Some(op) = FilterOp::from_name(&stripped[..end])
+        {
+            self.pos += end + 2;
+            return Ok(op);
+        }
+        Err(self.error("expected an operator"))
+    }
+
+    fn parse_value(&mut self) -> AppResult<String> {
+        self.skip_whitespace();
+        if let Some(quote) = self.peek().filter(|c| *c == '\'' || *c == '"') {
+            self.pos += 1;
+            let start = self.pos;
+            let Some(len) = self.input[start..].find(quote) else {
+                return Err(self.error("unterminated quoted value"));
+            };
+            self.pos += len + 1;
+            return Ok(self.input[start..start + len].to_string());
+        }
+
+        let start = self.pos;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| !matches!(c, ';' | ',' | '(' | ')' | '\'' | '"'))
+        {
+            self.pos += c.len_utf8();
+        }
+        let value = self.input[start..self.pos].trim();
+        if value.is_empty() {
+            return Err(self.error("expected a value"));
+        }
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Customer, Order};
+
+    fn parse<T: Resource>(raw: &str) -> FilterExpr {
+        FilterExpr::parse::<T>(Some(raw))
+            .expect("filter parses")
+            .expect("filter is not blank")
+    }
+
+    fn error<T: Resource>(raw: &str) -> String {
+        match FilterExpr::parse::<T>(Some(raw)) {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+    }
+
+    fn compare(expr: &FilterExpr) -> (&'static str, FilterOp, &SqlValue) {
+        match expr {
+            FilterExpr::Predicate(Predicate::Compare {
+                field, op, value, ..
+            }) => (field.name, *op, value),
+            other => panic!("expected a comparison, got {:?}", other),
+        }
+    }
+
+    fn text(value: &SqlValue) -> &str {
+        match value {
+            SqlValue::Text(v) => v,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn blank_input_is_no_filter() {
+        assert!(FilterExpr::parse::<Customer>(None).unwrap().is_none());
+        assert!(FilterExpr::parse::<Customer>(Some("  ")).unwrap().is_none());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse::<Customer>("state==SP;city==santos,state==RJ");
+        let FilterExpr::Or(items) = &expr else {
+            panic!("expected OR at the top, got {:?}", expr);
+        };
+        assert_eq!(items.len(), 2);
+        let FilterExpr::And(left) = &items[0] else {
+            panic!("expected AND on the left, got {:?}", items[0]);
+        };
+        assert_eq!(compare(&left[0]).0, "customer_state");
+        assert_eq!(compare(&left[1]).0, "customer_city");
+        assert_eq!(text(compare(&items[1]).2), "RJ");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let expr = parse::<Customer>("state==SP;(city==santos,city==campinas)");
+        let FilterExpr::And(items) = &expr else {
+            panic!("expected AND at the top, got {:?}", expr);
+        };
+        assert_eq!(compare(&items[0]).0, "customer_state");
+        assert!(matches!(&items[1], FilterExpr::Or(inner) if inner.len() == 2));
+    }
+
+    #[test]
+    fn quoted_values_keep_separators() {
+        let expr = parse::<Customer>("city=='sao paulo; centro,(sul)'");
+        assert_eq!(text(compare(&expr).2), "sao paulo; centro,(sul)");
+
+        let expr = parse::<Customer>(r#"city=in=("a,b",c)"#);
+        let FilterExpr::Predicate(Predicate::List { values, .. }) = &expr else {
+            panic!("expected a list, got {:?}", expr);
+        };
+        let values: Vec<_> = values.iter().map(text).collect();
+        assert_eq!(values, ["a,b", "c"]);
+
+        let expr = parse::<Customer>("state=in=SP");
+        assert!(
+            matches!(&expr, FilterExpr::Predicate(Predicate::List { values, .. }) if values.len() == 1)
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_rejected() {
+        assert!(error::<Customer>("city=='santos").contains("unterminated"));
+    }
+
+    #[test]
+    fn aliases_resolve_to_fields() {
+        let expr = parse::<Customer>("zip==01001");
+        assert_eq!(compare(&expr).0, "customer_zip_code_prefix");
+        let expr = parse::<Order>("status!=canceled");
+        assert_eq!(compare(&expr).0, "order_status");
+        assert_eq!(compare(&expr).1, FilterOp::Ne);
+    }
+
+    #[test]
+    fn city_and_state_match_like_their_parameters() {
+        let expr = parse::<Customer>("city==São Paulo;state==sp;zip==01001");
+        let FilterExpr::And(items) = &expr else {
+            panic!("expected AND, got {:?}", expr);
+        };
+        let matching: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                FilterExpr::Predicate(Predicate::Compare { matching, .. }) => *matching,
+                other => panic!("expected a comparison, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            matching,
+            [
+                Some(FilterMatch::Normalized("customer_city_normalized")),
+                Some(FilterMatch::Uppercase),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn wildcards_become_like_patterns() {
+        let expr = parse::<Customer>("city==sao_*");
+        let FilterExpr::Predicate(Predicate::Like {
+            negated, pattern, ..
+        }) = &expr
+        else {
+            panic!("expected a LIKE, got {:?}", expr);
+        };
+        assert!(!negated);
+        assert_eq!(pattern, "sao\\_%");
+    }
+
+    #[test]
+    fn null_operator_takes_a_boolean() {
+        let expr = parse::<Order>("order_delivered_customer_date=null=true");
+        assert!(matches!(
+            expr,
+            FilterExpr::Predicate(Predicate::Null { is_null: true, .. })
+        ));
+        assert!(error::<Order>("order_delivered_customer_date=null=yes").contains("true or false"));
+    }
+
+    #[test]
+    fn timestamps_accept_datetimes_and_dates() {
+        let midnight = chrono::NaiveDate::from_ymd_opt(2018, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let noon = midnight + chrono::Duration::hours(12);
+
+        for (raw, expected) in [
+            ("order_purchase_timestamp=ge=2018-01-01", midnight),
+            ("order_purchase_timestamp=ge=2018-01-01T12:00:00", noon),
+            ("order_purchase_timestamp=ge='2018-01-01 12:00:00'", noon),
+        ] {
+            let expr = parse::<Order>(raw);
+            let (_, op, value) = compare(&expr);
+            assert_eq!(op, FilterOp::Ge);
+            assert!(
+                matches!(value, SqlValue::Timestamp(t) if *t == expected),
+                "{}: {:?}",
+                raw,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        assert!(
+            error::<Order>("order_purchase_timestamp=lt=20180101")
+                .contains("not a valid value for 'order_purchase_timestamp'")
+        );
+        assert!(error::<Order>("order_purchase_timestamp=lt=2018-13-01").contains("not a valid"));
+    }
+
+    #[test]
+    fn numeric_values_on_text_fields_stay_text() {
+        let expr = parse::<Customer>("zip=ge=01001");
+        let (_, op, value) = compare(&expr);
+        assert_eq!(op, FilterOp::Ge);
+        assert_eq!(text(value), "01001");
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(error::<Customer>("password==x").contains("unknown field 'password'"));
+        assert!(error::<Customer>("status==delivered").contains("unknown field"));
+    }
+
+    #[test]
+    fn unknown_operators_are_rejected() {
+        assert!(error::<Customer>("state=like=SP").contains("expected an operator"));
+        assert!(error::<Customer>("state=SP").contains("expected an operator"));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        assert!(error::<Customer>("state==").contains("expected a value"));
+        assert!(error::<Customer>("(state==SP").contains("expected ')'"));
+        assert!(error::<Customer>("state==SP)").contains("unexpected input"));
+    }
+
+    #[test]
+    fn single_value_operators_reject_lists() {
+        assert!(error::<Customer>("state==(SP,RJ)").contains("expects a single value"));
+    }
+
+    #[test]
+    fn length_is_limited() {
+        let at_limit = format!("city=={}", "a".repeat(MAX_FILTER_LENGTH - 6));
+        assert!(FilterExpr::parse::<Customer>(Some(&at_limit)).is_ok());
+
+        let too_long = format!("city=={}", "a".repeat(MAX_FILTER_LENGTH));
+        assert!(error::<Customer>(&too_long).contains("exceeds"));
+    }
+
+    #[test]
+    fn nesting_depth_is_limited() {
+        let nested = |depth: usize| format!("{}state==SP{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(FilterExpr::parse::<Customer>(Some(&nested(MAX_FILTER_DEPTH))).is_ok());
+        assert!(error::<Customer>(&nested(MAX_FILTER_DEPTH + 1)).contains("nested too deeply"));
+    }
+
+    #[test]
+    fn list_size_is_limited() {
+        let list = |n: usize| {
+            let values: Vec<_> = (0..n).map(|i| format!("c{}", i)).collect();
+            format!("city=in=({})", values.join(","))
+        };
+        assert!(FilterExpr::parse::<Customer>(Some(&list(MAX_LIST_VALUES))).is_ok());
+        assert!(error::<Customer>(&list(MAX_LIST_VALUES + 1)).contains("at most 100 values"));
+    }
+}
@@ -1,19 +1,40 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
+    extract::State,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
 };
 
 use serde::{Serialize, de::DeserializeOwned};
 use tracing::error;
 
-use crate::error::{AppError, AppResult};
+use crate::error::{AppError, AppResult, ProblemDetails};
+use crate::extract::{Json, Path, Query};
 use crate::models::{
-    CreateCustomerDto, CreateOrderDto, CreateSellerDto, Customer, LocationSearchQuery, Order,
-    OrderSearchQuery, PaginatedResponse, PaginationParams, Seller, UpdateCustomerDto,
+    AnalyticsResponse, BatchResponse, CohortMatrix, CohortQuery, CreateCustomerDto, CreateOrderDto,
+    CreateSellerDto, Customer, CustomerRfm, CustomerWithIncludes, DeliveryAnalytics,
+    DeliveryAnalyticsQuery, DeliveryEstimateQuery, DetailQuery, ExportFormat, FieldsQuery,
+    FlowMatrix, FlowQuery, FunnelQuery, GeolocationRecord, Heatmap, HeatmapQuery,
+    LocationSearchQuery, LookupRequest, NearbyQuery, NearbySellersResponse, Order, OrderFunnel,
+    OrderItem, OrderPayment, OrderReview, OrderSearchQuery, OrderTimeseries, OrderTimeseriesQuery,
+    OrderWithIncludes, PaginatedResponse, PaginationParams, PaymentAnalytics,
+    PaymentAnalyticsQuery, Product, RefreshReport, ReviewAnalytics, ReviewAnalyticsQuery,
+    RfmRecomputeResult, RfmSegments, SearchQuery, SearchResults, Seller, SellerDeliveryEstimate,
+    SellerRating, SellerRatingQuery, Sparse, UpdateCustomerDto, split_ids,
 };
+use crate::routes::CURRENT_VERSION;
 use crate::state::AppState;
 
+#[utoipa::path(
+    post,
+    path = "/customers",
+    tag = "customers",
+    request_body = CreateCustomerDto,
+    responses(
+        (status = 201, description = "Customer created", body = Customer),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Already exists", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn create_customer_handler(
     State(state): State<AppState>,
     Json(payload): Json<CreateCustomerDto>,
@@ -22,22 +43,90 @@ pub async fn create_customer_handler(
     Ok((StatusCode::CREATED, Json(customer)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/customers",
+    tag = "customers",
+    params(LocationSearchQuery),
+    responses(
+        (status = 200, description = "Customer page; a `BatchResponse` when `ids` is given", body = PaginatedResponse<CustomerWithIncludes>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_customers_handler(
     State(state): State<AppState>,
     Query(query): Query<LocationSearchQuery>,
-) -> AppResult<Json<PaginatedResponse<Customer>>> {
+) -> AppResult<Response> {
+    if let Some(ids) = query.ids.as_deref() {
+        let response = state
+            .customer_service
+            .lookup_customers(split_ids(ids), &query.detail())
+            .await?;
+        return Ok(Json(response).into_response());
+    }
+
     let response = state.customer_service.get_customers(query).await?;
+    Ok(Json(response).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/customers:lookup",
+    tag = "customers",
+    params(DetailQuery),
+    request_body = LookupRequest,
+    responses(
+        (status = 200, description = "Customers in request order", body = BatchResponse<CustomerWithIncludes>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn lookup_customers_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DetailQuery>,
+    Json(payload): Json<LookupRequest>,
+) -> AppResult<Json<BatchResponse<CustomerWithIncludes>>> {
+    let response = state
+        .customer_service
+        .lookup_customers(payload.ids, &query)
+        .await?;
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/customers/{id}",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id"), DetailQuery),
+    responses(
+        (status = 200, description = "Customer", body = CustomerWithIncludes),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_customer_by_id_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
-) -> AppResult<Json<Customer>> {
-    let customer = state.customer_service.get_customer_by_id(&id).await?;
+    Query(query): Query<DetailQuery>,
+) -> AppResult<Json<CustomerWithIncludes>> {
+    let customer = state
+        .customer_service
+        .get_customer_by_id(&id, &query)
+        .await?;
     Ok(Json(customer))
 }
 
+#[utoipa::path(
+    put,
+    path = "/customers/{id}",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id")),
+    request_body = UpdateCustomerDto,
+    responses(
+        (status = 200, description = "Updated customer", body = Customer),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn update_customer_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
@@ -47,6 +136,16 @@ pub async fn update_customer_handler(
     Ok((StatusCode::OK, Json(customer)))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/customers/{id}",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id")),
+    responses(
+        (status = 204, description = "Customer deleted"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn delete_customer_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
@@ -55,18 +154,61 @@ pub async fn delete_customer_handler(
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    get,
+    path = "/customers/{id}/orders",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id"), PaginationParams, FieldsQuery),
+    responses(
+        (status = 200, description = "Orders of the customer", body = PaginatedResponse<Order>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_customer_orders_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
     Query(pagination): Query<PaginationParams>,
-) -> AppResult<Json<PaginatedResponse<Order>>> {
+    Query(query): Query<FieldsQuery>,
+) -> AppResult<Json<PaginatedResponse<Sparse<Order>>>> {
     let response = state
         .order_service
-        .get_orders_by_customer(&id, &pagination)
+        .get_orders_by_customer(&id, &pagination, query.fields.as_deref())
         .await?;
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/customers/{id}/nearby-sellers",
+    tag = "customers",
+    params(("id" = String, Path, description = "Customer id"), NearbyQuery),
+    responses(
+        (status = 200, description = "Sellers near the customer", body = NearbySellersResponse),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_customer_nearby_sellers_handler(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<NearbyQuery>,
+) -> AppResult<Json<NearbySellersResponse>> {
+    let response = state.geo_service.sellers_near_customer(&id, query).await?;
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sellers",
+    tag = "sellers",
+    request_body = CreateSellerDto,
+    responses(
+        (status = 201, description = "Seller created", body = Seller),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Already exists", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn create_seller_handler(
     State(state): State<AppState>,
     Json(payload): Json<CreateSellerDto>,
@@ -75,22 +217,126 @@ pub async fn create_seller_handler(
     Ok((StatusCode::CREATED, Json(seller)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/sellers",
+    tag = "sellers",
+    params(LocationSearchQuery),
+    responses(
+        (status = 200, description = "Seller page; a `BatchResponse` when `ids` is given", body = PaginatedResponse<Seller>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_sellers_handler(
     State(state): State<AppState>,
     Query(query): Query<LocationSearchQuery>,
-) -> AppResult<Json<PaginatedResponse<Seller>>> {
+) -> AppResult<Response> {
+    if let Some(ids) = query.ids.as_deref() {
+        let response = state
+            .seller_service
+            .lookup_sellers(split_ids(ids), &query.detail())
+            .await?;
+        return Ok(Json(response).into_response());
+    }
+
     let response = state.seller_service.get_sellers(query).await?;
+    Ok(Json(response).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/sellers:lookup",
+    tag = "sellers",
+    params(DetailQuery),
+    request_body = LookupRequest,
+    responses(
+        (status = 200, description = "Sellers in request order", body = BatchResponse<Seller>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn lookup_sellers_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DetailQuery>,
+    Json(payload): Json<LookupRequest>,
+) -> AppResult<Json<BatchResponse<Sparse<Seller>>>> {
+    let response = state
+        .seller_service
+        .lookup_sellers(payload.ids, &query)
+        .await?;
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/sellers/nearby",
+    tag = "sellers",
+    params(NearbyQuery),
+    responses(
+        (status = 200, description = "Sellers near a zip code", body = NearbySellersResponse),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_nearby_sellers_handler(
+    State(state): State<AppState>,
+    Query(query): Query<NearbyQuery>,
+) -> AppResult<Json<NearbySellersResponse>> {
+    let response = state.geo_service.sellers_near_zip(query).await?;
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sellers/{id}",
+    tag = "sellers",
+    params(("id" = String, Path, description = "Seller id"), DetailQuery),
+    responses(
+        (status = 200, description = "Seller", body = Seller),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_seller_by_id_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
-) -> AppResult<Json<Seller>> {
-    let seller = state.seller_service.get_seller_by_id(&id).await?;
+    Query(query): Query<DetailQuery>,
+) -> AppResult<Json<Sparse<Seller>>> {
+    let seller = state.seller_service.get_seller_by_id(&id, &query).await?;
     Ok(Json(seller))
 }
 
+#[utoipa::path(
+    get,
+    path = "/sellers/{id}/rating",
+    tag = "sellers",
+    params(("id" = String, Path, description = "Seller id"), SellerRatingQuery),
+    responses(
+        (status = 200, description = "Review scores of the seller", body = SellerRating),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_seller_rating_handler(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<SellerRatingQuery>,
+) -> AppResult<Json<SellerRating>> {
+    let rating = state.seller_service.seller_rating(&id, &query).await?;
+    Ok(Json(rating))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders",
+    tag = "orders",
+    request_body = CreateOrderDto,
+    responses(
+        (status = 201, description = "Order created", body = Order),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Already exists", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn create_order_handler(
     State(state): State<AppState>,
     Json(payload): Json<CreateOrderDto>,
@@ -99,26 +345,356 @@ pub async fn create_order_handler(
     Ok((StatusCode::CREATED, Json(order)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/orders",
+    tag = "orders",
+    params(OrderSearchQuery),
+    responses(
+        (status = 200, description = "Order page; a `BatchResponse` when `ids` is given", body = PaginatedResponse<OrderWithIncludes>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_orders_handler(
     State(state): State<AppState>,
     Query(query): Query<OrderSearchQuery>,
-) -> AppResult<Json<PaginatedResponse<Order>>> {
+) -> AppResult<Response> {
+    if let Some(ids) = query.ids.as_deref() {
+        let response = state
+            .order_service
+            .lookup_orders(split_ids(ids), &query.detail())
+            .await?;
+        return Ok(Json(response).into_response());
+    }
+
     let response = state.order_service.get_orders(query).await?;
+    Ok(Json(response).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders:lookup",
+    tag = "orders",
+    params(DetailQuery),
+    request_body = LookupRequest,
+    responses(
+        (status = 200, description = "Orders in request order", body = BatchResponse<OrderWithIncludes>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn lookup_orders_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DetailQuery>,
+    Json(payload): Json<LookupRequest>,
+) -> AppResult<Json<BatchResponse<OrderWithIncludes>>> {
+    let response = state
+        .order_service
+        .lookup_orders(payload.ids, &query)
+        .await?;
     Ok(Json(response))
 }
 
+#[utoipa::path(
+    get,
+    path = "/orders/{id}",
+    tag = "orders",
+    params(("id" = String, Path, description = "Order id"), DetailQuery),
+    responses(
+        (status = 200, description = "Order", body = OrderWithIncludes),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn get_order_by_id_handler(
     Path(id): Path<String>,
     State(state): State<AppState>,
-) -> AppResult<Json<Order>> {
-    let order = state.order_service.get_order_by_id(&id).await?;
+    Query(query): Query<DetailQuery>,
+) -> AppResult<Json<OrderWithIncludes>> {
+    let order = state.order_service.get_order_by_id(&id, &query).await?;
     Ok(Json(order))
 }
 
-pub async fn load_data_from_csv_handler() -> AppResult<impl IntoResponse> {
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching customers, sellers and orders", body = SearchResults),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn search_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> AppResult<Json<SearchResults>> {
+    let results = state.search_service.search(query).await?;
+    Ok(Json(results))
+}
+
+#[utoipa::path(
+    get,
+    path = "/geo/heatmap",
+    tag = "geo",
+    params(HeatmapQuery),
+    responses(
+        (status = 200, description = "GeoJSON FeatureCollection", body = Heatmap, content_type = "application/geo+json"),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn heatmap_handler(
+    State(state): State<AppState>,
+    Query(query): Query<HeatmapQuery>,
+) -> AppResult<Response> {
+    let heatmap = state.geo_service.heatmap(query).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(heatmap),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/estimates/delivery",
+    tag = "estimates",
+    params(DeliveryEstimateQuery),
+    responses(
+        (status = 200, description = "Predicted delivery window", body = SellerDeliveryEstimate),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delivery_estimate_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DeliveryEstimateQuery>,
+) -> AppResult<Json<SellerDeliveryEstimate>> {
+    let estimate = state.estimate_service.delivery(query).await?;
+    Ok(Json(estimate))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/delivery",
+    tag = "analytics",
+    params(DeliveryAnalyticsQuery),
+    responses(
+        (status = 200, description = "Delivery performance", body = AnalyticsResponse<DeliveryAnalytics>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delivery_analytics_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DeliveryAnalyticsQuery>,
+) -> AppResult<Json<AnalyticsResponse<DeliveryAnalytics>>> {
+    let analytics = state.analytics_service.delivery(query).await?;
+    Ok(Json(analytics))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/orders/timeseries",
+    tag = "analytics",
+    params(OrderTimeseriesQuery),
+    responses(
+        (status = 200, description = "Orders per interval", body = AnalyticsResponse<OrderTimeseries>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn order_timeseries_handler(
+    State(state): State<AppState>,
+    Query(query): Query<OrderTimeseriesQuery>,
+) -> AppResult<Json<AnalyticsResponse<OrderTimeseries>>> {
+    let timeseries = state.analytics_service.order_timeseries(query).await?;
+    Ok(Json(timeseries))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/cohorts",
+    tag = "analytics",
+    params(CohortQuery),
+    responses(
+        (status = 200, description = "Retention by first-purchase month", body = AnalyticsResponse<CohortMatrix>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn cohorts_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CohortQuery>,
+) -> AppResult<Json<AnalyticsResponse<CohortMatrix>>> {
+    let matrix = state.analytics_service.cohorts(query).await?;
+    Ok(Json(matrix))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/funnel",
+    tag = "analytics",
+    params(FunnelQuery),
+    responses(
+        (status = 200, description = "Order fulfilment funnel", body = AnalyticsResponse<OrderFunnel>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn funnel_handler(
+    State(state): State<AppState>,
+    Query(query): Query<FunnelQuery>,
+) -> AppResult<Json<AnalyticsResponse<OrderFunnel>>> {
+    let funnel = state.analytics_service.funnel(query).await?;
+    Ok(Json(funnel))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/flows",
+    tag = "analytics",
+    params(FlowQuery),
+    responses(
+        (status = 200, description = "Seller to customer state flows", content((AnalyticsResponse<FlowMatrix> = "application/json"), (String = "text/csv"))),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn flows_handler(
+    State(state): State<AppState>,
+    Query(query): Query<FlowQuery>,
+) -> AppResult<Response> {
+    let flows = state.analytics_service.state_flows(&query).await?;
+
+    match query.format.unwrap_or_default() {
+        ExportFormat::Json => {
+            let matrix = flows.map(|f| FlowMatrix::from_flows(query.from, query.to, &f));
+            Ok(Json(matrix).into_response())
+        }
+        ExportFormat::Csv => {
+            let mut response = csv_response("flows.csv", &flows.data)?;
+            if let Some(refreshed) = flows.last_refreshed_at {
+                let value = refreshed
+                    .and_utc()
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string();
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response.headers_mut().insert(header::LAST_MODIFIED, value);
+                }
+            }
+            Ok(response)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/reviews",
+    tag = "analytics",
+    params(ReviewAnalyticsQuery),
+    responses(
+        (status = 200, description = "Review scores against delivery lateness", body = AnalyticsResponse<ReviewAnalytics>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn reviews_analytics_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ReviewAnalyticsQuery>,
+) -> AppResult<Json<AnalyticsResponse<ReviewAnalytics>>> {
+    let analytics = state.analytics_service.reviews(query).await?;
+    Ok(Json(analytics))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/payments",
+    tag = "analytics",
+    params(PaymentAnalyticsQuery),
+    responses(
+        (status = 200, description = "Payment types and installments", body = AnalyticsResponse<PaymentAnalytics>),
+        (status = 400, description = "Invalid request", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn payments_analytics_handler(
+    State(state): State<AppState>,
+    Query(query): Query<PaymentAnalyticsQuery>,
+) -> AppResult<Json<AnalyticsResponse<PaymentAnalytics>>> {
+    let analytics = state.analytics_service.payments(query).await?;
+    Ok(Json(analytics))
+}
+
+#[utoipa::path(
+    get,
+    path = "/people/{id}/rfm",
+    tag = "analytics",
+    params(("id" = String, Path, description = "Customer unique id")),
+    responses(
+        (status = 200, description = "RFM profile", body = CustomerRfm),
+        (status = 404, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn person_rfm_handler(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<CustomerRfm>> {
+    let rfm = state.analytics_service.person_rfm(&id).await?;
+    Ok(Json(rfm))
+}
+
+#[utoipa::path(
+    get,
+    path = "/analytics/rfm/segments",
+    tag = "analytics",
+    responses(
+        (status = 200, description = "People per RFM segment", body = AnalyticsResponse<RfmSegments>),
+    )
+)]
+pub async fn rfm_segments_handler(
+    State(state): State<AppState>,
+) -> AppResult<Json<AnalyticsResponse<RfmSegments>>> {
+    let segments = state.analytics_service.rfm_segments().await?;
+    Ok(Json(segments))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/rfm/recompute",
+    tag = "admin",
+    responses(
+        (status = 200, description = "RFM scores recomputed", body = RfmRecomputeResult),
+    )
+)]
+pub async fn recompute_rfm_handler(
+    State(state): State<AppState>,
+) -> AppResult<Json<RfmRecomputeResult>> {
+    let result = state.analytics_service.recompute_rfm().await?;
+    Ok(Json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/analytics/refresh",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Analytics views refreshed", body = RefreshReport),
+    )
+)]
+pub async fn refresh_analytics_handler(
+    State(state): State<AppState>,
+) -> AppResult<Json<RefreshReport>> {
+    let report = state.analytics_service.refresh_all().await?;
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/load-data",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Counts of the records loaded from the Olist CSV files and of the rows skipped as unparseable"),
+    )
+)]
+pub async fn load_data_from_csv_handler(
+    State(state): State<AppState>,
+) -> AppResult<impl IntoResponse> {
     let client = reqwest::Client::new();
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let base_url = format!("http://localhost:{}", port);
+    let base_url = format!("http://localhost:{}{}", port, CURRENT_VERSION);
 
     let mut total_success = 0;
     let mut total_error = 0;
@@ -153,13 +729,96 @@ pub async fn load_data_from_csv_handler() -> AppResult<impl IntoResponse> {
     total_success += success;
     total_error += error;
 
+    // Load order items, linking orders to sellers
+    let (items, item_skipped) =
+        read_csv_records::<OrderItem>("data/olist_order_items_dataset.csv")?;
+    let item_count = state.order_service.import_items(items).await?;
+
+    // Load the product catalog and the reviews of the orders above
+    let (products, product_skipped) =
+        read_csv_records::<Product>("data/olist_products_dataset.csv")?;
+    let product_count = state.order_service.import_products(products).await?;
+    let (reviews, review_skipped) =
+        read_csv_records::<OrderReview>("data/olist_order_reviews_dataset.csv")?;
+    let review_count = state.order_service.import_reviews(reviews).await?;
+
+    // Load payments of the orders above
+    let (payments, payment_skipped) =
+        read_csv_records::<OrderPayment>("data/olist_order_payments_dataset.csv")?;
+    let payment_count = state.order_service.import_payments(payments).await?;
+
+    // Load zip code centroids used by proximity search
+    let (geolocations, centroid_skipped) =
+        read_csv_records::<GeolocationRecord>("data/olist_geolocation_dataset.csv")?;
+    let centroid_count = state.geo_service.import_centroids(geolocations).await?;
+
+    // Rebuild the analytics views and RFM scores once everything is in
+    let refresh = state.analytics_service.refresh_all().await?;
+
     Ok(Json(serde_json::json!({
         "message": "Data load processed",
         "success_count": total_success,
-        "error_count": total_error
+        "error_count": total_error,
+        "item_count": item_count,
+        "item_skipped": item_skipped,
+        "product_count": product_count,
+        "product_skipped": product_skipped,
+        "review_count": review_count,
+        "review_skipped": review_skipped,
+        "payment_count": payment_count,
+        "payment_skipped": payment_skipped,
+        "centroid_count": centroid_count,
+        "centroid_skipped": centroid_skipped,
+        "analytics_refreshed": refresh.datasets
     })))
 }
 
+fn csv_response<T: Serialize>(file_name: &str, rows: &[T]) -> AppResult<Response> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|e| AppError::InternalError(format!("Failed to write CSV row: {}", e)))?;
+    }
+    let body = writer
+        .into_inner()
+        .map_err(|e| AppError::InternalError(format!("Failed to finish CSV output: {}", e)))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Reads every record of a CSV file, along with the number of rows skipped
+/// because they failed to parse.
+fn read_csv_records<T: DeserializeOwned>(file_path: &str) -> AppResult<(Vec<T>, usize)> {
+    let mut rdr = csv::Reader::from_path(file_path).map_err(|e| {
+        error!("Failed to open CSV file {}: {}", file_path, e);
+        AppError::ConfigError(format!("Failed to open CSV file: {}", e))
+    })?;
+
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for result in rdr.deserialize() {
+        match result {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                error!("Failed to parse CSV record in {}: {}", file_path, e);
+                skipped += 1;
+            }
+        }
+    }
+    Ok((records, skipped))
+}
+
 async fn load_csv_data<T>(
     client: &reqwest::Client,
     url: &str,
@@ -212,3 +871,63 @@ where
     }
     Ok((success_count, error_count))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_csv_records_counts_skipped_rows() {
+        let path = std::env::temp_dir().join(format!("order_items_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "order_id,order_item_id,product_id,seller_id,shipping_limit_date,price,freight_value\n\
+             o1,1,p1,s1,2017-09-19 09:45:35,58.90,13.29\n\
+             o1,2,p1,s1,not a date,58.90,13.29\n\
+             o2,1,p2,s2,2017-05-03 11:05:13,239.90,19.93\n\
+             o3,x,p3,s3,2018-01-18 14:48:30,199.00,17.87\n",
+        )
+        .unwrap();
+
+        let result = read_csv_records::<OrderItem>(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let (items, skipped) = result.unwrap();
+        let ids: Vec<_> = items
+            .iter()
+            .map(|i| (i.order_id.as_str(), i.order_item_id))
+            .collect();
+        assert_eq!(ids, [("o1", 1), ("o2", 1)]);
+        assert_eq!(skipped, 2);
+    }
+
+    #[test]
+    fn read_csv_records_parses_olist_reviews() {
+        let path = std::env::temp_dir().join(format!("order_reviews_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "review_id,order_id,review_score,review_comment_title,review_comment_message,\
+             review_creation_date,review_answer_timestamp\n\
+             r1,o1,4,,,2018-01-18 00:00:00,2018-01-18 21:46:59\n\
+             r2,o2,5,,\"Recebi bem antes do prazo\",2018-03-10 00:00:00,\n\
+             r3,o3,1,,,10/03/2018,\n",
+        )
+        .unwrap();
+
+        let result = read_csv_records::<OrderReview>(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let (reviews, skipped) = result.unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(reviews.len(), 2);
+        assert!(reviews[0].review_answer_timestamp.is_some());
+        assert_eq!(reviews[1].review_answer_timestamp, None);
+        assert_eq!(reviews[1].review_comment_title, None);
+    }
+
+    #[test]
+    fn read_csv_records_fails_on_missing_file() {
+        let result = read_csv_records::<OrderItem>("data/does_not_exist.csv");
+        assert!(matches!(result, Err(AppError::ConfigError(_))));
+    }
+}
@@ -1,31 +1,23 @@
 mod config;
 mod error;
+mod extract;
+mod filter;
 mod handlers;
 mod models;
+mod openapi;
 mod repositories;
+mod routes;
 mod services;
 mod state;
 
-use axum::{
-    Router,
-    routing::{delete, get, post, put},
-};
 use dotenvy::dotenv;
 use sqlx::postgres::PgPoolOptions;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, time::Duration};
 use tokio::signal;
 use tracing::info;
 
 use crate::config::{create_cors_layer, load_config};
 use crate::error::AppError;
-use crate::handlers::{
-    create_customer_handler, create_order_handler, create_seller_handler, delete_customer_handler,
-    get_customer_by_id_handler, get_customer_orders_handler, get_customers_handler,
-    get_order_by_id_handler, get_orders_handler, get_seller_by_id_handler, get_sellers_handler,
-    load_data_from_csv_handler, update_customer_handler,
-};
-use crate::repositories::{PgCustomerRepository, PgOrderRepository, PgSellerRepository};
-use crate::services::{CustomerService, OrderService, SellerService};
 use crate::state::AppState;
 
 #[tokio::main]
@@ -50,37 +42,24 @@ async fn main() -> std::result::Result<(), AppError> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let customer_repository = PgCustomerRepository::new(pool.clone());
-    let customer_service = CustomerService::new(Arc::new(customer_repository));
+    let app_state = AppState::new(pool);
 
-    let seller_repository = PgSellerRepository::new(pool.clone());
-    let seller_service = SellerService::new(Arc::new(seller_repository));
-
-    let order_repository = PgOrderRepository::new(pool);
-    let order_service = OrderService::new(Arc::new(order_repository));
-
-    let app_state = AppState {
-        customer_service,
-        seller_service,
-        order_service,
-    };
+    if let Some(every) = config.analytics_refresh_interval {
+        info!("Refreshing analytics every {}s", every.as_secs());
+        tokio::spawn(
+            app_state
+                .analytics_service
+                .clone()
+                .run_refresh_schedule(every),
+        );
+    }
 
-    let app = Router::new()
-        .route("/load-data", post(load_data_from_csv_handler))
-        .route("/customers", post(create_customer_handler))
-        .route("/customers", get(get_customers_handler))
-        .route("/customers/{id}", get(get_customer_by_id_handler))
-        .route("/customers/{id}", put(update_customer_handler))
-        .route("/customers/{id}", delete(delete_customer_handler))
-        .route("/customers/{id}/orders", get(get_customer_orders_handler))
-        .route("/sellers", post(create_seller_handler))
-        .route("/sellers", get(get_sellers_handler))
-        .route("/sellers/{id}", get(get_seller_by_id_handler))
-        .route("/orders", post(create_order_handler))
-        .route("/orders", get(get_orders_handler))
-        .route("/orders/{id}", get(get_order_by_id_handler))
-        .with_state(app_state)
-        .layer(cors_layer);
+    let app = routes::app(
+        app_state,
+        config.unversioned_deprecated_since,
+        config.unversioned_sunset,
+    )
+    .layer(cors_layer);
 
     let addr: SocketAddr = format!("0.0.0.0:{}", config.port)
         .parse()
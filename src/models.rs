@@ -1,22 +1,68 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
-#[derive(Debug, Serialize)]
+use crate::error::{AppError, AppResult};
+use crate::filter::FilterExpr;
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PaginationMeta {
-    pub total_records: i64,
-    pub page: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_records: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
     pub page_size: u32,
-    pub total_pages: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_pages: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<String>,
+    /// How `total_records` was obtained; absent on cursor pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_mode: Option<CountMode>,
+    /// Set instead of the totals when counting was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_next: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+/// How page/page_size listings compute `total_records`: `exact` runs a
+/// `COUNT(*)`, `estimated` reads the planner's row estimate and `none` skips
+/// counting altogether.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    #[default]
+    Exact,
+    Estimated,
+    None,
+}
+
+/// Total rows matched by an offset listing, as obtained under its
+/// [`CountMode`].
+#[derive(Debug, Clone, Copy)]
+pub enum RowCount {
+    Exact(i64),
+    Estimated(i64),
+    Skipped { has_next: bool },
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaginationParams {
     pub page: Option<u32>,
     pub page_size: Option<u32>,
+    pub count: Option<CountMode>,
 }
 
 impl PaginationParams {
+    pub fn count_mode(&self) -> CountMode {
+        self.count.unwrap_or_default()
+    }
+
     pub fn normalize(&self) -> (i64, i64, u32, u32) {
         let page = self.page.unwrap_or(1).max(1);
         let page_size = self.page_size.unwrap_or(10).clamp(1, 100);
@@ -28,170 +74,3313 @@ impl PaginationParams {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PaginatedResponse<T> {
     pub data: Vec<T>,
     pub meta: PaginationMeta,
 }
 
 impl<T> PaginatedResponse<T> {
-    pub fn new(data: Vec<T>, count: i64, page: u32, page_size: u32) -> Self {
-        let total_pages = if count == 0 {
-            1
-        } else {
-            (count as f64 / page_size as f64).ceil() as u32
+    pub fn new(data: Vec<T>, count: RowCount, page: u32, page_size: u32) -> Self {
+        let (total_records, count_mode, has_next) = match count {
+            RowCount::Exact(total) => (Some(total), CountMode::Exact, None),
+            RowCount::Estimated(total) => (Some(total), CountMode::Estimated, None),
+            RowCount::Skipped { has_next } => (None, CountMode::None, Some(has_next)),
         };
+        let total_pages = total_records.map(|count| {
+            if count == 0 {
+                1
+            } else {
+                (count as f64 / page_size as f64).ceil() as u32
+            }
+        });
 
         Self {
             data,
             meta: PaginationMeta {
-                total_records: count,
-                page,
+                total_records,
+                page: Some(page),
                 page_size,
                 total_pages,
+                next_cursor: None,
+                prev_cursor: None,
+                count_mode: Some(count_mode),
+                has_next,
             },
         }
     }
 }
 
-#[derive(Debug, Deserialize, Default)]
-pub struct LocationFilter {
-    pub city: Option<String>,
-    pub state: Option<String>,
+impl<T> PaginatedResponse<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> PaginatedResponse<U> {
+        PaginatedResponse {
+            data: self.data.into_iter().map(f).collect(),
+            meta: self.meta,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct LocationSearchQuery {
-    pub page: Option<u32>,
-    pub page_size: Option<u32>,
-    pub city: Option<String>,
-    pub state: Option<String>,
+impl<T: Resource> PaginatedResponse<T> {
+    /// Builds a cursor page from the rows returned by a keyset query, where
+    /// `has_more` tells whether further rows exist in the direction travelled.
+    pub fn from_keyset(
+        data: Vec<T>,
+        has_more: bool,
+        keyset: &KeysetParams,
+        sort: &SortSpec,
+    ) -> Self {
+        let behind = keyset.has_rows_behind();
+        let (has_next, has_prev) = match keyset.direction() {
+            CursorDirection::Next => (has_more, behind),
+            CursorDirection::Prev => (behind, has_more),
+        };
+
+        let mut next_cursor = data
+            .last()
+            .filter(|_| has_next)
+            .map(|row| Cursor::at(row, sort, CursorDirection::Next).encode());
+        let mut prev_cursor = data
+            .first()
+            .filter(|_| has_prev)
+            .map(|row| Cursor::at(row, sort, CursorDirection::Prev).encode());
+
+        // An empty page has no row to anchor on, so the way back starts at
+        // the cursor's own row.
+        if data.is_empty() {
+            let back = keyset
+                .cursor
+                .as_ref()
+                .filter(|_| behind)
+                .map(|cursor| cursor.turned_back().encode());
+            match keyset.direction() {
+                CursorDirection::Next => prev_cursor = back,
+                CursorDirection::Prev => next_cursor = back,
+            }
+        }
+
+        Self {
+            data,
+            meta: PaginationMeta {
+                total_records: None,
+                page: None,
+                page_size: keyset.limit,
+                total_pages: None,
+                next_cursor,
+                prev_cursor,
+                count_mode: None,
+                has_next: None,
+            },
+        }
+    }
 }
 
-impl LocationSearchQuery {
-    pub fn pagination(&self) -> PaginationParams {
-        PaginationParams {
-            page: self.page,
-            page_size: self.page_size,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CursorDirection {
+    #[serde(rename = "n")]
+    Next,
+    #[serde(rename = "p")]
+    Prev,
+}
+
+/// Opaque position in a keyset-paginated listing: the sort key of the row the
+/// page starts after (or before, when walking backwards), together with the
+/// sort it was produced under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(rename = "s")]
+    pub sort: String,
+    #[serde(rename = "k")]
+    pub key: Vec<String>,
+    #[serde(rename = "d")]
+    pub direction: CursorDirection,
+    /// Whether the row at `key` belongs to the page. Only set on the
+    /// cursors leading back from an empty page.
+    #[serde(rename = "i", default, skip_serializing_if = "std::ops::Not::not")]
+    pub inclusive: bool,
+}
+
+impl Cursor {
+    pub fn at<T: Resource>(row: &T, sort: &SortSpec, direction: CursorDirection) -> Self {
+        Self {
+            sort: sort.to_string(),
+            key: sort
+                .keys
+                .iter()
+                .map(|k| row.field_value(k.field.name).unwrap_or_default())
+                .collect(),
+            direction,
+            inclusive: false,
         }
     }
 
-    pub fn filter(&self) -> LocationFilter {
-        LocationFilter {
-            city: self.city.clone(),
-            state: self.state.clone(),
+    /// Cursor walking back from this one's position, its own row included.
+    pub fn turned_back(&self) -> Self {
+        Self {
+            direction: match self.direction {
+                CursorDirection::Next => CursorDirection::Prev,
+                CursorDirection::Prev => CursorDirection::Next,
+            },
+            inclusive: true,
+            ..self.clone()
         }
     }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor is always serializable");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(raw: &str) -> AppResult<Self> {
+        URL_SAFE_NO_PAD
+            .decode(raw)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))
+    }
 }
 
-pub type CustomerFilter = LocationFilter;
-pub type SellerFilter = LocationFilter;
+#[derive(Debug)]
+pub struct KeysetParams {
+    pub cursor: Option<Cursor>,
+    pub limit: u32,
+}
 
-#[derive(Debug, FromRow, Serialize, Clone)]
-pub struct Customer {
-    pub customer_id: String,
-    pub customer_unique_id: String,
-    pub customer_zip_code_prefix: String,
-    pub customer_city: String,
-    pub customer_state: String,
+impl KeysetParams {
+    /// Typed sort key of the row the page starts after, if any. Cursors
+    /// produced under a different sort, or with malformed values, are rejected.
+    pub fn after_values(&self, sort: &SortSpec) -> AppResult<Option<Vec<SqlValue>>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+
+        if cursor.sort != sort.to_string() || cursor.key.len() != sort.keys.len() {
+            return Err(AppError::BadRequest(
+                "Cursor does not match the requested sort".to_string(),
+            ));
+        }
+
+        sort.keys
+            .iter()
+            .zip(&cursor.key)
+            .map(|(k, raw)| {
+                SqlValue::parse(k.field.kind, raw)
+                    .ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))
+            })
+            .collect::<AppResult<Vec<_>>>()
+            .map(Some)
+    }
+
+    pub fn direction(&self) -> CursorDirection {
+        self.cursor
+            .as_ref()
+            .map(|c| c.direction)
+            .unwrap_or(CursorDirection::Next)
+    }
+
+    pub fn inclusive(&self) -> bool {
+        self.cursor.as_ref().is_some_and(|c| c.inclusive)
+    }
+
+    /// Whether rows exist on the side the cursor came from. A cursor is taken
+    /// from a row of the page it leads away from, unless it is an inclusive
+    /// one leading back from an empty page.
+    pub fn has_rows_behind(&self) -> bool {
+        self.cursor.is_some() && !self.inclusive()
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
-pub struct CreateCustomerDto {
-    #[validate(length(min = 1, message = "ID cannot be empty"))]
-    pub customer_id: String,
-    #[validate(length(min = 1))]
-    pub customer_unique_id: String,
-    #[validate(length(min = 5, max = 10))]
-    pub customer_zip_code_prefix: String,
-    #[validate(length(min = 1))]
-    pub customer_city: String,
-    #[validate(length(min = 2, max = 2))]
-    pub customer_state: String,
+#[cfg(test)]
+mod cursor_tests {
+    use super::*;
+
+    fn seller(id: &str, city: &str) -> Seller {
+        Seller {
+            seller_id: id.to_string(),
+            seller_city: city.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn sort() -> SortSpec {
+        SortSpec::parse::<Seller>(Some("seller_city")).unwrap()
+    }
+
+    fn keyset(cursor: Option<Cursor>) -> KeysetParams {
+        KeysetParams { cursor, limit: 2 }
+    }
+
+    fn decode(raw: &Option<String>) -> Cursor {
+        Cursor::decode(raw.as_deref().expect("cursor")).unwrap()
+    }
+
+    fn page(
+        data: Vec<Seller>,
+        has_more: bool,
+        cursor: Option<Cursor>,
+    ) -> PaginatedResponse<Seller> {
+        PaginatedResponse::from_keyset(data, has_more, &keyset(cursor), &sort())
+    }
+
+    #[test]
+    fn cursor_round_trips_through_its_encoding() {
+        let cursor = Cursor::at(&seller("s1", "campinas"), &sort(), CursorDirection::Prev);
+        let decoded = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.sort, "seller_city,seller_id");
+        assert_eq!(decoded.key, ["campinas", "s1"]);
+        assert_eq!(decoded.direction, CursorDirection::Prev);
+        assert!(!decoded.inclusive);
+
+        let back = Cursor::decode(&cursor.turned_back().encode()).unwrap();
+        assert_eq!(back.key, ["campinas", "s1"]);
+        assert_eq!(back.direction, CursorDirection::Next);
+        assert!(back.inclusive);
+
+        assert!(Cursor::decode("not a cursor").is_err());
+    }
+
+    #[test]
+    fn cursor_from_another_sort_is_rejected() {
+        let cursor = Cursor::at(&seller("s1", "campinas"), &sort(), CursorDirection::Next);
+        let keyset = keyset(Some(cursor));
+
+        let values = keyset.after_values(&sort()).unwrap().unwrap();
+        assert!(
+            matches!(&values[..], [SqlValue::Text(city), SqlValue::Text(id)]
+            if city == "campinas" && id == "s1")
+        );
+
+        let other = SortSpec::parse::<Seller>(Some("-seller_city")).unwrap();
+        assert!(matches!(
+            keyset.after_values(&other),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(
+            keyset
+                .after_values(&SortSpec::parse::<Seller>(None).unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn first_page_links_forward_only() {
+        let response = page(vec![seller("s1", "a"), seller("s2", "b")], true, None);
+        assert_eq!(decode(&response.meta.next_cursor).key, ["b", "s2"]);
+        assert!(response.meta.prev_cursor.is_none());
+
+        let response = page(vec![seller("s1", "a")], false, None);
+        assert!(response.meta.next_cursor.is_none());
+        assert!(response.meta.prev_cursor.is_none());
+    }
+
+    #[test]
+    fn walking_forward_links_back_to_the_first_row() {
+        let cursor = Cursor::at(&seller("s2", "b"), &sort(), CursorDirection::Next);
+
+        let response = page(
+            vec![seller("s3", "c"), seller("s4", "d")],
+            true,
+            Some(cursor.clone()),
+        );
+        assert_eq!(decode(&response.meta.next_cursor).key, ["d", "s4"]);
+        let prev = decode(&response.meta.prev_cursor);
+        assert_eq!(prev.key, ["c", "s3"]);
+        assert_eq!(prev.direction, CursorDirection::Prev);
+
+        let response = page(vec![seller("s3", "c")], false, Some(cursor));
+        assert!(response.meta.next_cursor.is_none());
+        assert_eq!(decode(&response.meta.prev_cursor).key, ["c", "s3"]);
+    }
+
+    #[test]
+    fn walking_backward_links_forward_to_the_last_row() {
+        let cursor = Cursor::at(&seller("s3", "c"), &sort(), CursorDirection::Prev);
+
+        let response = page(
+            vec![seller("s1", "a"), seller("s2", "b")],
+            false,
+            Some(cursor.clone()),
+        );
+        let next = decode(&response.meta.next_cursor);
+        assert_eq!(next.key, ["b", "s2"]);
+        assert_eq!(next.direction, CursorDirection::Next);
+        assert!(response.meta.prev_cursor.is_none());
+
+        let response = page(
+            vec![seller("s1", "a"), seller("s2", "b")],
+            true,
+            Some(cursor),
+        );
+        assert!(response.meta.next_cursor.is_some());
+        assert_eq!(decode(&response.meta.prev_cursor).key, ["a", "s1"]);
+    }
+
+    #[test]
+    fn empty_page_links_back_to_the_cursor_row() {
+        let cursor = Cursor::at(&seller("s2", "b"), &sort(), CursorDirection::Next);
+        let response = page(Vec::new(), false, Some(cursor));
+        assert!(response.meta.next_cursor.is_none());
+        let prev = decode(&response.meta.prev_cursor);
+        assert_eq!(prev.key, ["b", "s2"]);
+        assert_eq!(prev.direction, CursorDirection::Prev);
+        assert!(prev.inclusive);
+
+        // The page it leads to ends at the cursor row, with nothing after it.
+        let response = page(vec![seller("s1", "a"), seller("s2", "b")], true, Some(prev));
+        assert!(response.meta.next_cursor.is_none());
+        assert_eq!(decode(&response.meta.prev_cursor).key, ["a", "s1"]);
+
+        let cursor = Cursor::at(&seller("s1", "a"), &sort(), CursorDirection::Prev);
+        let response = page(Vec::new(), false, Some(cursor));
+        assert!(response.meta.prev_cursor.is_none());
+        let next = decode(&response.meta.next_cursor);
+        assert_eq!(next.direction, CursorDirection::Next);
+        assert!(next.inclusive);
+
+        assert!(page(Vec::new(), false, None).meta.prev_cursor.is_none());
+    }
 }
 
-#[derive(Debug, Deserialize, Validate, Default)]
-pub struct UpdateCustomerDto {
-    #[validate(length(min = 1))]
-    pub customer_unique_id: Option<String>,
-    #[validate(length(min = 5, max = 10))]
-    pub customer_zip_code_prefix: Option<String>,
-    #[validate(length(min = 1))]
-    pub customer_city: Option<String>,
-    #[validate(length(min = 2, max = 2))]
-    pub customer_state: Option<String>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Timestamp,
 }
 
-#[derive(Debug, FromRow, Serialize, Clone)]
-pub struct Seller {
-    pub seller_id: String,
-    pub seller_zip_code_prefix: String,
-    pub seller_city: String,
-    pub seller_state: String,
+/// Describes a column exposed by a resource. `name` is both the JSON key and
+/// the column name in the resource's table.
+#[derive(Debug)]
+pub struct FieldMeta {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    pub sortable: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
-pub struct CreateSellerDto {
-    #[validate(length(min = 1, message = "ID cannot be empty"))]
-    pub seller_id: String,
-    #[validate(length(min = 5, max = 10))]
-    pub seller_zip_code_prefix: String,
-    #[validate(length(min = 1))]
-    pub seller_city: String,
-    #[validate(length(min = 2, max = 2))]
-    pub seller_state: String,
+/// How a filtered text field is compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMatch {
+    /// Through a generated lowercase, unaccented copy of the column, against
+    /// input normalized the same way.
+    Normalized(&'static str),
+    /// Against the input uppercased, as state codes are stored.
+    Uppercase,
 }
 
-#[derive(Debug, FromRow, Serialize, Clone)]
-pub struct Order {
-    pub order_id: String,
-    pub customer_id: String,
-    pub order_status: String,
-    pub order_purchase_timestamp: chrono::NaiveDateTime,
-    pub order_approved_at: chrono::NaiveDateTime,
-    pub order_delivered_carrier_date: Option<chrono::NaiveDateTime>,
-    pub order_delivered_customer_date: Option<chrono::NaiveDateTime>,
-    pub order_estimated_delivery_date: chrono::NaiveDateTime,
+/// Field metadata shared by the listing endpoints, used to validate and
+/// compile client-supplied query options.
+pub trait Resource {
+    const FIELDS: &'static [FieldMeta];
+    const PRIMARY_KEY: &'static str;
+    const DEFAULT_SORT: &'static str;
+    /// Short names accepted by `?filter=` in addition to the field names,
+    /// as `(alias, field)` pairs.
+    const FILTER_ALIASES: &'static [(&'static str, &'static str)] = &[];
+    /// Fields `?filter=` compares the way their dedicated query parameters
+    /// do, rather than by exact value.
+    const FILTER_MATCHES: &'static [(&'static str, FilterMatch)] = &[];
+
+    fn field(name: &str) -> Option<&'static FieldMeta> {
+        Self::FIELDS.iter().find(|f| f.name == name)
+    }
+
+    fn filter_field(name: &str) -> Option<&'static FieldMeta> {
+        let name = Self::FILTER_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, field)| field);
+        Self::field(name)
+    }
+
+    fn filter_match(name: &str) -> Option<FilterMatch> {
+        Self::FILTER_MATCHES
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, matching)| *matching)
+    }
+
+    /// Value of a field in its cursor representation.
+    fn field_value(&self, name: &str) -> Option<String>;
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
-pub struct CreateOrderDto {
-    #[validate(length(min = 1))]
-    pub order_id: String,
-    #[validate(length(min = 1))]
-    pub customer_id: String,
-    #[validate(length(min = 1))]
-    pub order_status: String,
-    pub order_purchase_timestamp: chrono::NaiveDateTime,
-    pub order_approved_at: chrono::NaiveDateTime,
-    pub order_delivered_carrier_date: Option<chrono::NaiveDateTime>,
-    pub order_delivered_customer_date: Option<chrono::NaiveDateTime>,
-    pub order_estimated_delivery_date: chrono::NaiveDateTime,
+/// Subset of a resource's fields requested with `?fields=`. `requested` is
+/// `None` when every field is wanted; `internal` lists columns a service needs
+/// to read (e.g. foreign keys for includes) without returning them.
+#[derive(Debug, Clone, Default)]
+pub struct FieldSet {
+    requested: Option<Arc<[&'static str]>>,
+    internal: Vec<&'static str>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-pub struct OrderFilter {
-    pub order_status: Option<String>,
+impl FieldSet {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma-separated list against the fields of `T`.
+    pub fn parse<T: Resource>(raw: Option<&str>) -> AppResult<Self> {
+        let Some(raw) = raw.map(str::trim).filter(|r| !r.is_empty()) else {
+            return Ok(Self::all());
+        };
+
+        let mut names: Vec<&'static str> = Vec::new();
+        for name in raw.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let field = T::field(name).ok_or_else(|| {
+                let allowed: Vec<_> = T::FIELDS.iter().map(|f| f.name).collect();
+                AppError::BadRequest(format!(
+                    "Unknown field '{}'. Allowed fields: {}",
+                    name,
+                    allowed.join(", ")
+                ))
+            })?;
+            if !names.contains(&field.name) {
+                names.push(field.name);
+            }
+        }
+
+        Ok(Self {
+            requested: Some(names.into()),
+            internal: Vec::new(),
+        })
+    }
+
+    /// Also selects `name` from the database, without serializing it unless
+    /// it was requested.
+    pub fn require(mut self, name: &'static str) -> Self {
+        self.internal.push(name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.requested
+            .as_ref()
+            .is_none_or(|names| names.contains(&name))
+    }
+
+    /// SELECT list for `T`: the requested fields plus any `required` ones the
+    /// repository needs (primary key, sort keys), in table order.
+    pub fn columns<T: Resource>(&self, required: &[&str]) -> String {
+        T::FIELDS
+            .iter()
+            .filter(|f| {
+                self.contains(f.name)
+                    || required.contains(&f.name)
+                    || self.internal.contains(&f.name)
+            })
+            .map(|f| f.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn apply<T>(&self, item: T) -> Sparse<T> {
+        Sparse {
+            item,
+            fields: self.clone(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct OrderSearchQuery {
-    pub page: Option<u32>,
-    pub page_size: Option<u32>,
-    pub order_status: Option<String>,
+/// A resource serialized with only the fields of its [`FieldSet`].
+#[derive(Debug)]
+pub struct Sparse<T> {
+    pub item: T,
+    fields: FieldSet,
 }
 
-impl OrderSearchQuery {
-    pub fn pagination(&self) -> PaginationParams {
-        PaginationParams {
-            page: self.page,
-            page_size: self.page_size,
+impl<T: Serialize> Serialize for Sparse<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.fields.requested.is_none() {
+            return self.item.serialize(serializer);
+        }
+
+        match serde_json::to_value(&self.item).map_err(serde::ser::Error::custom)? {
+            serde_json::Value::Object(mut map) => {
+                map.retain(|key, _| self.fields.contains(key));
+                map.serialize(serializer)
+            }
+            other => other.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod fieldset_tests {
+    use super::*;
+
+    fn seller() -> Seller {
+        Seller {
+            seller_id: "s1".to_string(),
+            seller_zip_code_prefix: "13023".to_string(),
+            seller_city: "campinas".to_string(),
+            seller_state: "SP".to_string(),
         }
     }
 
-    pub fn filter(&self) -> OrderFilter {
-        OrderFilter {
-            order_status: self.order_status.clone(),
+    #[test]
+    fn missing_or_blank_list_selects_every_field() {
+        for raw in [None, Some(""), Some("  ")] {
+            let fields = FieldSet::parse::<Seller>(raw).unwrap();
+            assert_eq!(
+                fields.columns::<Seller>(&[]),
+                "seller_id, seller_zip_code_prefix, seller_city, seller_state"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_trims_and_deduplicates_names() {
+        let fields =
+            FieldSet::parse::<Seller>(Some(" seller_state , seller_city,seller_state,")).unwrap();
+        assert!(fields.contains("seller_state"));
+        assert!(fields.contains("seller_city"));
+        assert!(!fields.contains("seller_id"));
+    }
+
+    #[test]
+    fn unknown_field_lists_the_allowed_ones() {
+        match FieldSet::parse::<Seller>(Some("seller_city,password")) {
+            Err(AppError::BadRequest(message)) => assert_eq!(
+                message,
+                "Unknown field 'password'. Allowed fields: \
+                 seller_id, seller_zip_code_prefix, seller_city, seller_state"
+            ),
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn columns_add_required_and_internal_fields_in_table_order() {
+        let fields = FieldSet::parse::<Seller>(Some("seller_state")).unwrap();
+        assert_eq!(fields.columns::<Seller>(&[]), "seller_state");
+        assert_eq!(
+            fields.columns::<Seller>(&["seller_id"]),
+            "seller_id, seller_state"
+        );
+
+        let fields = fields.require("seller_city");
+        assert_eq!(
+            fields.columns::<Seller>(&["seller_id"]),
+            "seller_id, seller_city, seller_state"
+        );
+    }
+
+    #[test]
+    fn sparse_serializes_only_requested_fields() {
+        let fields = FieldSet::parse::<Seller>(Some("seller_city"))
+            .unwrap()
+            .require("seller_id");
+        assert_eq!(
+            serde_json::to_value(fields.apply(seller())).unwrap(),
+            serde_json::json!({ "seller_city": "campinas" })
+        );
+
+        let all = serde_json::to_value(FieldSet::all().apply(seller())).unwrap();
+        assert_eq!(all, serde_json::to_value(seller()).unwrap());
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+}
+
+/// Query options accepted by the single-resource endpoints.
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DetailQuery {
+    pub fields: Option<String>,
+    pub include: Option<String>,
+    pub orders_limit: Option<u32>,
+}
+
+/// Maximum number of ids accepted by a batch lookup.
+pub const MAX_LOOKUP_IDS: usize = 100;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LookupRequest {
+    #[schema(min_items = 1, max_items = 100)]
+    pub ids: Vec<String>,
+}
+
+/// Splits `?ids=a,b,c` into its ids.
+pub fn split_ids(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Result of a batch lookup: found records in request order, and the ids
+/// that matched nothing.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResponse<T> {
+    pub data: Vec<T>,
+    pub missing: Vec<String>,
+}
+
+impl<T> BatchResponse<T> {
+    /// Orders `rows` after `ids` (already deduplicated), collecting the ids
+    /// with no matching row as missing.
+    pub fn from_rows(ids: &[String], rows: Vec<T>, id_of: impl Fn(&T) -> &str) -> Self {
+        let mut by_id: std::collections::HashMap<String, T> = rows
+            .into_iter()
+            .map(|row| (id_of(&row).to_string(), row))
+            .collect();
+
+        let mut data = Vec::with_capacity(ids.len());
+        let mut missing = Vec::new();
+        for id in ids {
+            match by_id.remove(id) {
+                Some(row) => data.push(row),
+                None => missing.push(id.clone()),
+            }
+        }
+
+        Self { data, missing }
+    }
+}
+
+/// Deduplicates lookup ids, keeping the first occurrence, and enforces the
+/// batch size limit.
+pub fn normalize_lookup_ids(ids: Vec<String>) -> AppResult<Vec<String>> {
+    let mut unique: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids.into_iter().map(|id| id.trim().to_string()) {
+        if !id.is_empty() && !unique.contains(&id) {
+            unique.push(id);
         }
     }
+
+    if unique.is_empty() || unique.len() > MAX_LOOKUP_IDS {
+        return Err(AppError::BadRequest(format!(
+            "Provide between 1 and {} ids",
+            MAX_LOOKUP_IDS
+        )));
+    }
+    Ok(unique)
+}
+
+#[cfg(test)]
+mod lookup_tests {
+    use super::*;
+
+    fn ids(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_keeps_first_occurrence_order() {
+        assert_eq!(
+            normalize_lookup_ids(ids(&["b", " a ", "b", "", "c", "a"])).unwrap(),
+            ["b", "a", "c"]
+        );
+        assert_eq!(split_ids(" b,a,, b "), ["b", "a", "b"]);
+    }
+
+    #[test]
+    fn normalize_enforces_the_batch_size() {
+        assert!(normalize_lookup_ids(Vec::new()).is_err());
+        assert!(normalize_lookup_ids(ids(&[" ", ""])).is_err());
+
+        let max: Vec<String> = (0..MAX_LOOKUP_IDS).map(|i| i.to_string()).collect();
+        assert_eq!(
+            normalize_lookup_ids(max.clone()).unwrap().len(),
+            MAX_LOOKUP_IDS
+        );
+
+        // Duplicates do not count towards the limit.
+        let mut repeated = max.clone();
+        repeated.push("0".to_string());
+        assert!(normalize_lookup_ids(repeated).is_ok());
+
+        let mut over = max;
+        over.push("extra".to_string());
+        assert!(matches!(
+            normalize_lookup_ids(over),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn from_rows_follows_request_order_and_reports_missing_ids() {
+        let rows = vec![("c", 3), ("a", 1), ("b", 2)];
+        let response =
+            BatchResponse::from_rows(&ids(&["b", "x", "a", "c", "y"]), rows, |row| row.0);
+        assert_eq!(response.data, [("b", 2), ("a", 1), ("c", 3)]);
+        assert_eq!(response.missing, ["x", "y"]);
+    }
+
+    #[test]
+    fn from_rows_with_no_matches_reports_every_id() {
+        let response =
+            BatchResponse::from_rows(&ids(&["a", "b"]), Vec::<(&str, i32)>::new(), |row| row.0);
+        assert!(response.data.is_empty());
+        assert_eq!(response.missing, ["a", "b"]);
+    }
+}
+
+/// Parses `?include=` against the relations a resource can embed.
+pub fn parse_includes(raw: Option<&str>, allowed: &[&'static str]) -> AppResult<Vec<&'static str>> {
+    let Some(raw) = raw.map(str::trim).filter(|r| !r.is_empty()) else {
+        return Ok(Vec::new());
+    };
+
+    raw.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            allowed.iter().copied().find(|a| *a == name).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Cannot include '{}'. Allowed relations: {}",
+                    name,
+                    if allowed.is_empty() {
+                        "none".to_string()
+                    } else {
+                        allowed.join(", ")
+                    }
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Number of embedded orders per customer for `?include=orders`.
+pub fn normalize_orders_limit(limit: Option<u32>) -> i64 {
+    limit.unwrap_or(5).clamp(1, 50) as i64
+}
+
+/// A value bound into dynamically built SQL, typed after the field it targets.
+#[derive(Debug, Clone)]
+pub enum SqlValue {
+    Text(String),
+    Timestamp(chrono::NaiveDateTime),
+}
+
+impl SqlValue {
+    pub fn parse(kind: FieldKind, raw: &str) -> Option<Self> {
+        match kind {
+            FieldKind::Text => Some(SqlValue::Text(raw.to_string())),
+            FieldKind::Timestamp => raw.parse().ok().map(SqlValue::Timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SortKey {
+    pub field: &'static FieldMeta,
+    pub descending: bool,
+}
+
+/// Validated sort order, always ending with the primary key so that pages are
+/// deterministic and usable as keyset positions.
+#[derive(Debug, Clone)]
+pub struct SortSpec {
+    pub keys: Vec<SortKey>,
+}
+
+impl SortSpec {
+    /// Parses `field,-field2` against the sortable fields of `T`, falling back
+    /// to the resource's default order when `raw` is empty.
+    pub fn parse<T: Resource>(raw: Option<&str>) -> AppResult<Self> {
+        let raw = raw
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .unwrap_or(T::DEFAULT_SORT);
+
+        let mut keys: Vec<SortKey> = Vec::new();
+        for token in raw.split(',').map(str::trim) {
+            let (name, descending) = match token.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (token.strip_prefix('+').unwrap_or(token), false),
+            };
+
+            let field = T::field(name).filter(|f| f.sortable).ok_or_else(|| {
+                let allowed: Vec<_> = T::FIELDS
+                    .iter()
+                    .filter(|f| f.sortable)
+                    .map(|f| f.name)
+                    .collect();
+                AppError::BadRequest(format!(
+                    "Cannot sort by '{}'. Allowed fields: {}",
+                    name,
+                    allowed.join(", ")
+                ))
+            })?;
+
+            if keys.iter().any(|k| k.field.name == field.name) {
+                return Err(AppError::BadRequest(format!(
+                    "Field '{}' appears more than once in sort",
+                    name
+                )));
+            }
+            keys.push(SortKey { field, descending });
+        }
+
+        if !keys.iter().any(|k| k.field.name == T::PRIMARY_KEY) {
+            let descending = keys.last().is_some_and(|k| k.descending);
+            let field = T::field(T::PRIMARY_KEY).expect("primary key is a listed field");
+            keys.push(SortKey { field, descending });
+        }
+
+        Ok(Self { keys })
+    }
+}
+
+impl SortSpec {
+    pub fn field_names(&self) -> Vec<&'static str> {
+        self.keys.iter().map(|k| k.field.name).collect()
+    }
+}
+
+impl std::fmt::Display for SortSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys: Vec<_> = self
+            .keys
+            .iter()
+            .map(|k| format!("{}{}", if k.descending { "-" } else { "" }, k.field.name))
+            .collect();
+        write!(f, "{}", keys.join(","))
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use super::*;
+
+    fn keys(sort: &SortSpec) -> Vec<(&'static str, bool)> {
+        sort.keys
+            .iter()
+            .map(|k| (k.field.name, k.descending))
+            .collect()
+    }
+
+    fn rejection<T: Resource>(raw: &str) -> String {
+        match SortSpec::parse::<T>(Some(raw)) {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_sortable_fields_are_accepted() {
+        assert_eq!(
+            rejection::<Seller>("seller_name"),
+            "Cannot sort by 'seller_name'. Allowed fields: \
+             seller_id, seller_zip_code_prefix, seller_city, seller_state"
+        );
+        assert!(
+            rejection::<Order>("order_delivered_carrier_date")
+                .starts_with("Cannot sort by 'order_delivered_carrier_date'")
+        );
+        assert_eq!(
+            rejection::<Seller>("seller_city,-seller_city"),
+            "Field 'seller_city' appears more than once in sort"
+        );
+        // Column names come from the metadata, so injection attempts are
+        // plain unknown fields.
+        assert!(rejection::<Seller>("seller_id; DROP TABLE sellers").starts_with("Cannot sort"));
+    }
+
+    #[test]
+    fn primary_key_is_appended_in_the_last_key_direction() {
+        let sort = SortSpec::parse::<Seller>(Some("seller_state,+seller_city")).unwrap();
+        assert_eq!(
+            keys(&sort),
+            [
+                ("seller_state", false),
+                ("seller_city", false),
+                ("seller_id", false)
+            ]
+        );
+
+        let sort = SortSpec::parse::<Customer>(None).unwrap();
+        assert_eq!(
+            keys(&sort),
+            [("customer_zip_code_prefix", true), ("customer_id", true)]
+        );
+        assert_eq!(sort.to_string(), "-customer_zip_code_prefix,-customer_id");
+
+        let sort = SortSpec::parse::<Seller>(Some("-seller_id,seller_city")).unwrap();
+        assert_eq!(keys(&sort), [("seller_id", true), ("seller_city", false)]);
+    }
+}
+
+#[derive(Debug)]
+pub enum PageRequest {
+    Offset(PaginationParams),
+    Keyset(KeysetParams),
+}
+
+impl PageRequest {
+    /// Picks cursor mode when `cursor` or `limit` is present, and the classic
+    /// page/page_size mode otherwise.
+    pub fn from_query(
+        page: Option<u32>,
+        page_size: Option<u32>,
+        cursor: Option<&str>,
+        limit: Option<u32>,
+        count: Option<CountMode>,
+    ) -> AppResult<Self> {
+        if cursor.is_none() && limit.is_none() {
+            return Ok(PageRequest::Offset(PaginationParams {
+                page,
+                page_size,
+                count,
+            }));
+        }
+
+        if page.is_some() || page_size.is_some() {
+            return Err(AppError::BadRequest(
+                "cursor/limit cannot be combined with page/page_size".to_string(),
+            ));
+        }
+        if count.is_some() {
+            return Err(AppError::BadRequest(
+                "count only applies to page/page_size pagination".to_string(),
+            ));
+        }
+
+        Ok(PageRequest::Keyset(KeysetParams {
+            cursor: cursor.map(Cursor::decode).transpose()?,
+            limit: limit.unwrap_or(10).clamp(1, 100),
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct LocationFilter {
+    pub city: Option<String>,
+    pub city_prefix: Option<String>,
+    pub state: Option<String>,
+    #[serde(skip)]
+    pub expr: Option<FilterExpr>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LocationSearchQuery {
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+    pub count: Option<CountMode>,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+    pub include: Option<String>,
+    pub orders_limit: Option<u32>,
+    /// Comma-separated ids; switches the listing to a batch lookup.
+    pub ids: Option<String>,
+    pub city: Option<String>,
+    pub city_prefix: Option<String>,
+    pub state: Option<String>,
+    /// Filter expression, see [`crate::filter`].
+    pub filter: Option<String>,
+}
+
+impl LocationSearchQuery {
+    /// Options that still apply when `ids` turns the listing into a lookup.
+    pub fn detail(&self) -> DetailQuery {
+        DetailQuery {
+            fields: self.fields.clone(),
+            include: self.include.clone(),
+            orders_limit: self.orders_limit,
+        }
+    }
+
+    pub fn page_request(&self) -> AppResult<PageRequest> {
+        PageRequest::from_query(
+            self.page,
+            self.page_size,
+            self.cursor.as_deref(),
+            self.limit,
+            self.count,
+        )
+    }
+
+    pub fn filter<T: Resource>(&self) -> AppResult<LocationFilter> {
+        Ok(LocationFilter {
+            city: self.city.clone(),
+            city_prefix: self.city_prefix.clone(),
+            state: self.state.as_deref().map(normalize_state),
+            expr: FilterExpr::parse::<T>(self.filter.as_deref())?,
+        })
+    }
+}
+
+/// State codes are stored uppercase (`SP`, `RJ`).
+pub fn normalize_state(state: &str) -> String {
+    state.trim().to_uppercase()
+}
+
+pub type CustomerFilter = LocationFilter;
+pub type SellerFilter = LocationFilter;
+
+const CURSOR_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+// Columns left out by a sparse fieldset fall back to their defaults.
+#[derive(Debug, FromRow, Serialize, Clone, Default, ToSchema)]
+#[sqlx(default)]
+pub struct Customer {
+    pub customer_id: String,
+    pub customer_unique_id: String,
+    pub customer_zip_code_prefix: String,
+    pub customer_city: String,
+    pub customer_state: String,
+}
+
+impl Resource for Customer {
+    const FIELDS: &'static [FieldMeta] = &[
+        FieldMeta {
+            name: "customer_id",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "customer_unique_id",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "customer_zip_code_prefix",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "customer_city",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "customer_state",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+    ];
+    const PRIMARY_KEY: &'static str = "customer_id";
+    const DEFAULT_SORT: &'static str = "-customer_zip_code_prefix";
+    const FILTER_ALIASES: &'static [(&'static str, &'static str)] = &[
+        ("city", "customer_city"),
+        ("state", "customer_state"),
+        ("zip", "customer_zip_code_prefix"),
+    ];
+    const FILTER_MATCHES: &'static [(&'static str, FilterMatch)] = &[
+        (
+            "customer_city",
+            FilterMatch::Normalized("customer_city_normalized"),
+        ),
+        ("customer_state", FilterMatch::Uppercase),
+    ];
+
+    fn field_value(&self, name: &str) -> Option<String> {
+        match name {
+            "customer_id" => Some(self.customer_id.clone()),
+            "customer_unique_id" => Some(self.customer_unique_id.clone()),
+            "customer_zip_code_prefix" => Some(self.customer_zip_code_prefix.clone()),
+            "customer_city" => Some(self.customer_city.clone()),
+            "customer_state" => Some(self.customer_state.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct CreateCustomerDto {
+    #[validate(length(min = 1, message = "ID cannot be empty"))]
+    #[schema(min_length = 1)]
+    pub customer_id: String,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub customer_unique_id: String,
+    #[validate(length(min = 5, max = 10))]
+    #[schema(min_length = 5, max_length = 10)]
+    pub customer_zip_code_prefix: String,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub customer_city: String,
+    #[validate(length(min = 2, max = 2))]
+    #[schema(min_length = 2, max_length = 2)]
+    pub customer_state: String,
+}
+
+#[derive(Debug, Deserialize, Validate, Default, ToSchema)]
+pub struct UpdateCustomerDto {
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub customer_unique_id: Option<String>,
+    #[validate(length(min = 5, max = 10))]
+    #[schema(min_length = 5, max_length = 10)]
+    pub customer_zip_code_prefix: Option<String>,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub customer_city: Option<String>,
+    #[validate(length(min = 2, max = 2))]
+    #[schema(min_length = 2, max_length = 2)]
+    pub customer_state: Option<String>,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Default, ToSchema)]
+#[sqlx(default)]
+pub struct Seller {
+    pub seller_id: String,
+    pub seller_zip_code_prefix: String,
+    pub seller_city: String,
+    pub seller_state: String,
+}
+
+impl Resource for Seller {
+    const FIELDS: &'static [FieldMeta] = &[
+        FieldMeta {
+            name: "seller_id",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "seller_zip_code_prefix",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "seller_city",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "seller_state",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+    ];
+    const PRIMARY_KEY: &'static str = "seller_id";
+    const DEFAULT_SORT: &'static str = "seller_id";
+    const FILTER_ALIASES: &'static [(&'static str, &'static str)] = &[
+        ("city", "seller_city"),
+        ("state", "seller_state"),
+        ("zip", "seller_zip_code_prefix"),
+    ];
+    const FILTER_MATCHES: &'static [(&'static str, FilterMatch)] = &[
+        (
+            "seller_city",
+            FilterMatch::Normalized("seller_city_normalized"),
+        ),
+        ("seller_state", FilterMatch::Uppercase),
+    ];
+
+    fn field_value(&self, name: &str) -> Option<String> {
+        match name {
+            "seller_id" => Some(self.seller_id.clone()),
+            "seller_zip_code_prefix" => Some(self.seller_zip_code_prefix.clone()),
+            "seller_city" => Some(self.seller_city.clone()),
+            "seller_state" => Some(self.seller_state.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct CreateSellerDto {
+    #[validate(length(min = 1, message = "ID cannot be empty"))]
+    #[schema(min_length = 1)]
+    pub seller_id: String,
+    #[validate(length(min = 5, max = 10))]
+    #[schema(min_length = 5, max_length = 10)]
+    pub seller_zip_code_prefix: String,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub seller_city: String,
+    #[validate(length(min = 2, max = 2))]
+    #[schema(min_length = 2, max_length = 2)]
+    pub seller_state: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Default, ToSchema)]
+#[sqlx(default)]
+pub struct Order {
+    pub order_id: String,
+    pub customer_id: String,
+    pub order_status: String,
+    pub order_purchase_timestamp: chrono::NaiveDateTime,
+    pub order_approved_at: chrono::NaiveDateTime,
+    pub order_delivered_carrier_date: Option<chrono::NaiveDateTime>,
+    pub order_delivered_customer_date: Option<chrono::NaiveDateTime>,
+    pub order_estimated_delivery_date: chrono::NaiveDateTime,
+}
+
+impl Resource for Order {
+    // Nullable delivery dates are not sortable: NULLs cannot take part in a
+    // keyset comparison.
+    const FIELDS: &'static [FieldMeta] = &[
+        FieldMeta {
+            name: "order_id",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "customer_id",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "order_status",
+            kind: FieldKind::Text,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "order_purchase_timestamp",
+            kind: FieldKind::Timestamp,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "order_approved_at",
+            kind: FieldKind::Timestamp,
+            sortable: true,
+        },
+        FieldMeta {
+            name: "order_delivered_carrier_date",
+            kind: FieldKind::Timestamp,
+            sortable: false,
+        },
+        FieldMeta {
+            name: "order_delivered_customer_date",
+            kind: FieldKind::Timestamp,
+            sortable: false,
+        },
+        FieldMeta {
+            name: "order_estimated_delivery_date",
+            kind: FieldKind::Timestamp,
+            sortable: true,
+        },
+    ];
+    const PRIMARY_KEY: &'static str = "order_id";
+    const DEFAULT_SORT: &'static str = "-order_purchase_timestamp";
+    const FILTER_ALIASES: &'static [(&'static str, &'static str)] = &[("status", "order_status")];
+
+    fn field_value(&self, name: &str) -> Option<String> {
+        let timestamp = |t: &chrono::NaiveDateTime| t.format(CURSOR_TIMESTAMP_FORMAT).to_string();
+        match name {
+            "order_id" => Some(self.order_id.clone()),
+            "customer_id" => Some(self.customer_id.clone()),
+            "order_status" => Some(self.order_status.clone()),
+            "order_purchase_timestamp" => Some(timestamp(&self.order_purchase_timestamp)),
+            "order_approved_at" => Some(timestamp(&self.order_approved_at)),
+            "order_delivered_carrier_date" => {
+                self.order_delivered_carrier_date.as_ref().map(timestamp)
+            }
+            "order_delivered_customer_date" => {
+                self.order_delivered_customer_date.as_ref().map(timestamp)
+            }
+            "order_estimated_delivery_date" => Some(timestamp(&self.order_estimated_delivery_date)),
+            _ => None,
+        }
+    }
+}
+
+/// A customer with the relations requested through `?include=`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CustomerWithIncludes {
+    #[serde(flatten)]
+    #[schema(value_type = Customer)]
+    pub customer: Sparse<Customer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orders: Option<Vec<Order>>,
+}
+
+/// An order with the relations requested through `?include=`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderWithIncludes {
+    #[serde(flatten)]
+    #[schema(value_type = Order)]
+    pub order: Sparse<Order>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer: Option<Customer>,
+}
+
+/// Deserializes timestamps written as in the Olist CSV files
+/// (`2017-10-02 10:56:33`), also accepting ISO 8601 (`2017-10-02T10:56:33`)
+/// as sent in JSON bodies.
+pub mod olist_timestamp {
+    use serde::{Deserialize, Deserializer, de::Error};
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    fn parse<E: Error>(raw: &str) -> Result<chrono::NaiveDateTime, E> {
+        chrono::NaiveDateTime::parse_from_str(raw, FORMAT)
+            .or_else(|_| raw.parse())
+            .map_err(|e| E::custom(format!("invalid timestamp '{}': {}", raw, e)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<chrono::NaiveDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        parse(&String::deserialize(deserializer)?)
+    }
+
+    /// Optional timestamps; an empty field is `None`.
+    pub mod option {
+        use serde::{Deserialize, Deserializer};
+
+        pub fn deserialize<'de, D>(
+            deserializer: D,
+        ) -> Result<Option<chrono::NaiveDateTime>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            match Option::<String>::deserialize(deserializer)?.as_deref() {
+                None | Some("") => Ok(None),
+                Some(raw) => super::parse(raw).map(Some),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize)]
+        struct Row {
+            #[serde(deserialize_with = "super::deserialize")]
+            created: chrono::NaiveDateTime,
+            #[serde(default, deserialize_with = "super::option::deserialize")]
+            answered: Option<chrono::NaiveDateTime>,
+        }
+
+        fn read(csv: &str) -> Result<Row, csv::Error> {
+            let data = format!("created,answered\n{}\n", csv);
+            csv::Reader::from_reader(data.as_bytes())
+                .deserialize()
+                .next()
+                .expect("one record")
+        }
+
+        fn at(raw: &str) -> chrono::NaiveDateTime {
+            chrono::NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S").unwrap()
+        }
+
+        #[test]
+        fn parses_csv_format() {
+            let row = read("2017-10-02 10:56:33,2017-10-04 19:55:00").unwrap();
+            assert_eq!(row.created, at("2017-10-02T10:56:33"));
+            assert_eq!(row.answered, Some(at("2017-10-04T19:55:00")));
+        }
+
+        #[test]
+        fn parses_iso_format() {
+            let row: Row = serde_json::from_str(
+                r#"{"created": "2017-10-02T10:56:33", "answered": "2017-10-04T19:55:00.5"}"#,
+            )
+            .unwrap();
+            assert_eq!(row.created, at("2017-10-02T10:56:33"));
+            assert!(row.answered.is_some());
+        }
+
+        #[test]
+        fn empty_or_missing_optional_field_is_none() {
+            assert_eq!(read("2017-10-02 10:56:33,").unwrap().answered, None);
+            let row: Row = serde_json::from_str(r#"{"created": "2017-10-02 10:56:33"}"#).unwrap();
+            assert_eq!(row.answered, None);
+        }
+
+        #[test]
+        fn rejects_malformed_and_empty_required_values() {
+            let error = read("02/10/2017 10:56,").unwrap_err().to_string();
+            assert!(
+                error.contains("invalid timestamp '02/10/2017 10:56'"),
+                "{}",
+                error
+            );
+            assert!(read("2017-10-02 10:56:33,2017-13-01 00:00:00").is_err());
+            assert!(read(",").is_err());
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct CreateOrderDto {
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub order_id: String,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub customer_id: String,
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub order_status: String,
+    #[serde(deserialize_with = "olist_timestamp::deserialize")]
+    pub order_purchase_timestamp: chrono::NaiveDateTime,
+    #[serde(deserialize_with = "olist_timestamp::deserialize")]
+    pub order_approved_at: chrono::NaiveDateTime,
+    #[serde(default, deserialize_with = "olist_timestamp::option::deserialize")]
+    pub order_delivered_carrier_date: Option<chrono::NaiveDateTime>,
+    #[serde(default, deserialize_with = "olist_timestamp::option::deserialize")]
+    pub order_delivered_customer_date: Option<chrono::NaiveDateTime>,
+    /// Estimated from delivery history when omitted, as a whole day like the
+    /// Olist estimates (midnight).
+    #[serde(default, deserialize_with = "olist_timestamp::option::deserialize")]
+    pub order_estimated_delivery_date: Option<chrono::NaiveDateTime>,
+    /// Seller shipping the order, used only to estimate the delivery date.
+    /// Without it the estimate uses the handling time of all sellers and the
+    /// transit time to the customer's state from anywhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub seller_id: Option<String>,
+}
+
+/// Inclusive range of calendar days.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+impl DateRange {
+    fn new(
+        name: &str,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> AppResult<Self> {
+        Self::checked(from, to, &format!("{}_from", name), &format!("{}_to", name))
+    }
+
+    /// Range taken from plain `from`/`to` parameters.
+    pub fn between(
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> AppResult<Self> {
+        Self::checked(from, to, "from", "to")
+    }
+
+    fn checked(
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+        from_name: &str,
+        to_name: &str,
+    ) -> AppResult<Self> {
+        if let (Some(from), Some(to)) = (from, to)
+            && from > to
+        {
+            return Err(AppError::BadRequest(format!(
+                "{} must not be after {}",
+                from_name, to_name
+            )));
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Lower bound as the first instant of `from`.
+    pub fn start(&self) -> Option<chrono::NaiveDateTime> {
+        self.from.and_then(|d| d.and_hms_opt(0, 0, 0))
+    }
+
+    /// Exclusive upper bound: the first instant of the day after `to`.
+    pub fn end(&self) -> Option<chrono::NaiveDateTime> {
+        self.to
+            .and_then(|d| d.succ_opt())
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OrderFilter {
+    pub order_status: Option<String>,
+    pub statuses: Vec<String>,
+    pub purchased: DateRange,
+    pub approved: DateRange,
+    pub delivered: DateRange,
+    pub late: Option<bool>,
+    pub undelivered: Option<bool>,
+    pub customer_state: Option<String>,
+    pub customer_city: Option<String>,
+    pub expr: Option<FilterExpr>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderSearchQuery {
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+    pub count: Option<CountMode>,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    pub sort: Option<String>,
+    pub fields: Option<String>,
+    pub include: Option<String>,
+    /// Comma-separated ids; switches the listing to a batch lookup.
+    pub ids: Option<String>,
+    pub order_status: Option<String>,
+    /// Comma-separated list of statuses, e.g. `delivered,shipped`.
+    pub status: Option<String>,
+    pub purchased_from: Option<chrono::NaiveDate>,
+    pub purchased_to: Option<chrono::NaiveDate>,
+    pub approved_from: Option<chrono::NaiveDate>,
+    pub approved_to: Option<chrono::NaiveDate>,
+    pub delivered_from: Option<chrono::NaiveDate>,
+    pub delivered_to: Option<chrono::NaiveDate>,
+    /// Only orders delivered after their estimated date (`false`: on time).
+    pub late: Option<bool>,
+    /// Only orders not yet delivered to the customer (`false`: delivered).
+    pub undelivered: Option<bool>,
+    pub customer_state: Option<String>,
+    pub customer_city: Option<String>,
+    /// Filter expression, see [`crate::filter`].
+    pub filter: Option<String>,
+}
+
+impl OrderSearchQuery {
+    /// Options that still apply when `ids` turns the listing into a lookup.
+    pub fn detail(&self) -> DetailQuery {
+        DetailQuery {
+            fields: self.fields.clone(),
+            include: self.include.clone(),
+            orders_limit: None,
+        }
+    }
+
+    pub fn page_request(&self) -> AppResult<PageRequest> {
+        PageRequest::from_query(
+            self.page,
+            self.page_size,
+            self.cursor.as_deref(),
+            self.limit,
+            self.count,
+        )
+    }
+
+    pub fn filter(&self) -> AppResult<OrderFilter> {
+        let statuses = self
+            .status
+            .as_deref()
+            .map(|raw| {
+                raw.split(',')
+                    .map(|s| s.trim().to_lowercase())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(OrderFilter {
+            order_status: self.order_status.clone(),
+            statuses,
+            purchased: DateRange::new("purchased", self.purchased_from, self.purchased_to)?,
+            approved: DateRange::new("approved", self.approved_from, self.approved_to)?,
+            delivered: DateRange::new("delivered", self.delivered_from, self.delivered_to)?,
+            late: self.late,
+            undelivered: self.undelivered,
+            customer_state: self.customer_state.as_deref().map(normalize_state),
+            customer_city: self.customer_city.clone(),
+            expr: FilterExpr::parse::<Order>(self.filter.as_deref())?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    #[validate(length(
+        min = 3,
+        max = 100,
+        message = "Search term must be 3 to 100 characters"
+    ))]
+    #[param(min_length = 3, max_length = 100)]
+    pub q: String,
+    #[validate(range(min = 1, max = 50))]
+    #[param(minimum = 1, maximum = 50)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct SearchHit {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+    pub matched_field: String,
+    pub matched_value: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+}
+
+/// Mean coordinates of a zip code prefix, derived from the geolocation dataset.
+#[derive(Debug, FromRow, Serialize, Clone, ToSchema)]
+pub struct ZipCentroid {
+    pub zip_code_prefix: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub city: String,
+    pub state: String,
+    pub sample_count: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeolocationRecord {
+    pub geolocation_zip_code_prefix: String,
+    pub geolocation_lat: f64,
+    pub geolocation_lng: f64,
+    pub geolocation_city: String,
+    pub geolocation_state: String,
+}
+
+/// Brazilian zip code prefixes are the first five digits of the CEP; inputs
+/// such as `1001` or `01001-000` are accepted.
+pub fn normalize_zip_prefix(raw: &str) -> AppResult<String> {
+    let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        1..=5 => Ok(format!("{:0>5}", digits)),
+        8 => Ok(digits[..5].to_string()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid zip code prefix '{}'",
+            raw
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NearbyQuery {
+    pub zip: Option<String>,
+    #[validate(range(min = 1.0, max = 1000.0))]
+    #[param(minimum = 1.0, maximum = 1000.0)]
+    pub radius_km: Option<f64>,
+    #[validate(range(min = 1, max = 100))]
+    #[param(minimum = 1, maximum = 100)]
+    pub limit: Option<u32>,
+}
+
+impl NearbyQuery {
+    pub fn radius_km(&self) -> f64 {
+        self.radius_km.unwrap_or(50.0)
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(20) as i64
+    }
+}
+
+/// Latitude/longitude rectangle enclosing a search circle, used to narrow
+/// candidates with an index before computing exact distances.
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub max_latitude: f64,
+    pub min_longitude: f64,
+    pub max_longitude: f64,
+}
+
+const KM_PER_DEGREE_LATITUDE: f64 = 111.045;
+
+impl BoundingBox {
+    pub fn around(latitude: f64, longitude: f64, radius_km: f64) -> Self {
+        let lat_delta = radius_km / KM_PER_DEGREE_LATITUDE;
+        let lng_delta =
+            radius_km / (KM_PER_DEGREE_LATITUDE * latitude.to_radians().cos().abs().max(0.01));
+
+        Self {
+            min_latitude: latitude - lat_delta,
+            max_latitude: latitude + lat_delta,
+            min_longitude: longitude - lng_delta,
+            max_longitude: longitude + lng_delta,
+        }
+    }
+}
+
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct NearbySeller {
+    pub seller_id: String,
+    pub seller_zip_code_prefix: String,
+    pub seller_city: String,
+    pub seller_state: String,
+    pub distance_km: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NearbySellersResponse {
+    pub origin: ZipCentroid,
+    pub radius_km: f64,
+    pub data: Vec<NearbySeller>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryGroupBy {
+    State,
+    Month,
+    Status,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryAnalyticsQuery {
+    pub group_by: Option<DeliveryGroupBy>,
+    /// Purchase date range, inclusive.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+/// Delivery performance of one group of orders. Durations are in days;
+/// statistics over delivered orders are `None` when the group has none.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct DeliveryStats {
+    pub key: String,
+    pub order_count: i64,
+    pub delivered_count: i64,
+    /// Share of delivered orders that arrived by their estimated date.
+    pub on_time_rate: Option<f64>,
+    /// Purchase to customer delivery.
+    pub avg_delivery_days: Option<f64>,
+    pub median_delivery_days: Option<f64>,
+    pub p90_delivery_days: Option<f64>,
+    /// Days past the estimate, averaged over late orders only.
+    pub avg_days_late: Option<f64>,
+    /// Approval to carrier handoff.
+    pub avg_carrier_handoff_days: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveryAnalytics {
+    pub group_by: Option<DeliveryGroupBy>,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub groups: Vec<DeliveryStats>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeseriesInterval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeseriesInterval {
+    /// Unit name understood by Postgres `date_trunc` and intervals.
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            TimeseriesInterval::Day => "day",
+            TimeseriesInterval::Week => "week",
+            TimeseriesInterval::Month => "month",
+        }
+    }
+
+    fn approx_days(self) -> i64 {
+        match self {
+            TimeseriesInterval::Day => 1,
+            TimeseriesInterval::Week => 7,
+            TimeseriesInterval::Month => 28,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeseriesSplit {
+    Status,
+}
+
+const MAX_TIMESERIES_BUCKETS: i64 = 3660;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderTimeseriesQuery {
+    pub interval: Option<TimeseriesInterval>,
+    /// Purchase date range, inclusive. Defaults to the span of matching orders.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    /// Customer state.
+    pub state: Option<String>,
+    pub split_by: Option<TimeseriesSplit>,
+}
+
+impl OrderTimeseriesQuery {
+    /// Purchase range of the series. `span` is the first and last purchase
+    /// date of the matching orders; it stands in for open bounds when
+    /// checking the bucket cap, since those default to the data's extent.
+    pub fn range(
+        &self,
+        span: Option<(chrono::NaiveDate, chrono::NaiveDate)>,
+    ) -> AppResult<DateRange> {
+        let range = DateRange::between(self.from, self.to)?;
+        let from = self.from.or(span.map(|(first, _)| first));
+        let to = self.to.or(span.map(|(_, last)| last));
+        if let (Some(from), Some(to)) = (from, to) {
+            let buckets = (to - from).num_days() / self.interval().approx_days() + 1;
+            if buckets > MAX_TIMESERIES_BUCKETS {
+                return Err(AppError::BadRequest(format!(
+                    "Range spans more than {} buckets; use a coarser interval",
+                    MAX_TIMESERIES_BUCKETS
+                )));
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn interval(&self) -> TimeseriesInterval {
+        self.interval.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod timeseries_tests {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+
+    fn first() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2016, 9, 4).unwrap()
+    }
+
+    /// Last day of the longest daily series starting on `first()`.
+    fn last_allowed() -> NaiveDate {
+        first() + Duration::days(MAX_TIMESERIES_BUCKETS - 1)
+    }
+
+    fn query(
+        interval: TimeseriesInterval,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> OrderTimeseriesQuery {
+        OrderTimeseriesQuery {
+            interval: Some(interval),
+            from,
+            to,
+            state: None,
+            split_by: None,
+        }
+    }
+
+    fn is_capped(result: AppResult<DateRange>) -> bool {
+        matches!(result, Err(AppError::BadRequest(msg)) if msg.contains("buckets"))
+    }
+
+    #[test]
+    fn explicit_range_is_capped_at_max_buckets() {
+        let day = TimeseriesInterval::Day;
+        let at_cap = query(day, Some(first()), Some(last_allowed()));
+        assert!(at_cap.range(None).is_ok());
+
+        let over_cap = query(day, Some(first()), Some(last_allowed() + Duration::days(1)));
+        assert!(is_capped(over_cap.range(None)));
+        // An explicit range ignores the data's span.
+        assert!(is_capped(over_cap.range(Some((first(), first())))));
+    }
+
+    #[test]
+    fn open_bounds_are_capped_by_the_data_span() {
+        let day = TimeseriesInterval::Day;
+        let at_cap = Some((first(), last_allowed()));
+        let over_cap = Some((first(), last_allowed() + Duration::days(1)));
+
+        assert!(query(day, None, None).range(at_cap).is_ok());
+        assert!(is_capped(query(day, None, None).range(over_cap)));
+
+        // Only the open bound is taken from the data.
+        let from = Some(first());
+        assert!(query(day, from, None).range(at_cap).is_ok());
+        assert!(is_capped(query(day, from, None).range(over_cap)));
+        let early = Some((first() - Duration::days(1), last_allowed()));
+        assert!(query(day, from, None).range(early).is_ok());
+        assert!(is_capped(
+            query(day, None, Some(last_allowed())).range(early)
+        ));
+    }
+
+    #[test]
+    fn open_range_without_data_is_not_capped() {
+        let range = query(TimeseriesInterval::Day, None, None)
+            .range(None)
+            .unwrap();
+        assert_eq!((range.from, range.to), (None, None));
+    }
+
+    #[test]
+    fn coarser_interval_allows_longer_ranges() {
+        let to = Some(last_allowed() + Duration::days(1));
+        let from = Some(first());
+        assert!(is_capped(
+            query(TimeseriesInterval::Day, from, to).range(None)
+        ));
+        assert!(
+            query(TimeseriesInterval::Week, from, to)
+                .range(None)
+                .is_ok()
+        );
+        assert!(
+            query(TimeseriesInterval::Month, from, to)
+                .range(None)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn inverted_range_is_rejected() {
+        let result =
+            query(TimeseriesInterval::Day, Some(last_allowed()), Some(first())).range(None);
+        assert!(
+            matches!(result, Err(AppError::BadRequest(msg)) if msg.contains("must not be after"))
+        );
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct TimeseriesRow {
+    pub status: Option<String>,
+    pub bucket: chrono::NaiveDate,
+    pub order_count: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesPoint {
+    /// First day of the bucket (weeks start on Monday).
+    pub bucket: chrono::NaiveDate,
+    pub order_count: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesSeries {
+    /// Order status of the series; `None` when the series is not split.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    pub points: Vec<TimeseriesPoint>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderTimeseries {
+    pub interval: TimeseriesInterval,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub state: Option<String>,
+    pub series: Vec<TimeseriesSeries>,
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CohortQuery {
+    /// Customer state of the purchases taken into account.
+    pub state: Option<String>,
+    /// Range of first-purchase dates; cohorts are the months it covers.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    /// Months reported after the first purchase.
+    #[validate(range(min = 1, max = 36))]
+    #[param(minimum = 1, maximum = 36)]
+    pub periods: Option<u32>,
+}
+
+impl CohortQuery {
+    pub fn periods(&self) -> u32 {
+        self.periods.unwrap_or(12)
+    }
+}
+
+/// People of a cohort who purchased `period` months after their first
+/// purchase. `observed_periods` is how many months of data exist after the
+/// cohort month.
+#[derive(Debug, FromRow)]
+pub struct CohortCell {
+    pub cohort: chrono::NaiveDate,
+    pub period: i32,
+    pub people: i64,
+    pub observed_periods: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CohortRow {
+    /// First-purchase month, as `YYYY-MM`.
+    pub cohort: String,
+    pub size: i64,
+    /// Repeat purchasers per month since the first purchase, starting with
+    /// month 0. `None` for months past the end of the data.
+    pub people: Vec<Option<i64>>,
+    /// `people` as a share of `size`.
+    pub retention: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CohortMatrix {
+    pub state: Option<String>,
+    pub periods: u32,
+    pub cohorts: Vec<CohortRow>,
+}
+
+impl CohortMatrix {
+    /// Builds the matrix from cells ordered by cohort and period. Months a
+    /// cohort has data for but no purchasers in read 0; months past the end
+    /// of the data are `None`.
+    pub fn from_cells(cells: Vec<CohortCell>, state: Option<String>, periods: u32) -> Self {
+        let mut cohorts: Vec<CohortRow> = Vec::new();
+        let mut current: Option<chrono::NaiveDate> = None;
+        for cell in cells {
+            if current != Some(cell.cohort) {
+                current = Some(cell.cohort);
+                let observed = cell.observed_periods.clamp(0, periods as i32) as usize;
+                let mut people = vec![None; periods as usize + 1];
+                people[..=observed].fill(Some(0));
+                cohorts.push(CohortRow {
+                    cohort: cell.cohort.format("%Y-%m").to_string(),
+                    size: 0,
+                    people,
+                    retention: Vec::new(),
+                });
+            }
+            if let Some(row) = cohorts.last_mut()
+                && let Some(slot) = row.people.get_mut(cell.period as usize)
+            {
+                *slot = Some(cell.people);
+                if cell.period == 0 {
+                    row.size = cell.people;
+                }
+            }
+        }
+
+        for row in &mut cohorts {
+            let size = row.size;
+            row.retention = row
+                .people
+                .iter()
+                .map(|people| {
+                    people.map(|p| {
+                        if size == 0 {
+                            0.0
+                        } else {
+                            p as f64 / size as f64
+                        }
+                    })
+                })
+                .collect();
+        }
+
+        Self {
+            state,
+            periods,
+            cohorts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod cohort_tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn cell(month: u32, period: i32, people: i64, observed_periods: i32) -> CohortCell {
+        CohortCell {
+            cohort: NaiveDate::from_ymd_opt(2018, month, 1).unwrap(),
+            period,
+            people,
+            observed_periods,
+        }
+    }
+
+    #[test]
+    fn months_without_data_are_none_and_empty_months_zero() {
+        let cells = vec![
+            cell(6, 0, 10, 2),
+            cell(6, 2, 1, 2),
+            cell(7, 0, 4, 1),
+            cell(8, 0, 5, 0),
+        ];
+        let matrix = CohortMatrix::from_cells(cells, Some("SP".to_string()), 3);
+
+        let rows: Vec<_> = matrix
+            .cohorts
+            .iter()
+            .map(|row| (row.cohort.as_str(), row.size, row.people.clone()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("2018-06", 10, vec![Some(10), Some(0), Some(1), None]),
+                ("2018-07", 4, vec![Some(4), Some(0), None, None]),
+                ("2018-08", 5, vec![Some(5), None, None, None]),
+            ]
+        );
+        assert_eq!(
+            matrix.cohorts[0].retention,
+            [Some(1.0), Some(0.0), Some(0.1), None]
+        );
+        assert_eq!(matrix.state.as_deref(), Some("SP"));
+        assert_eq!(matrix.periods, 3);
+    }
+
+    #[test]
+    fn observed_periods_are_capped_at_the_requested_periods() {
+        let cells = vec![cell(1, 0, 2, 20), cell(1, 1, 1, 20), cell(1, 5, 1, 20)];
+        let matrix = CohortMatrix::from_cells(cells, None, 2);
+
+        // Month 5 lies outside the requested periods and is dropped.
+        assert_eq!(matrix.cohorts[0].people, [Some(2), Some(1), Some(0)]);
+        assert_eq!(
+            matrix.cohorts[0].retention,
+            [Some(1.0), Some(0.5), Some(0.0)]
+        );
+    }
+
+    #[test]
+    fn no_cells_is_an_empty_matrix() {
+        assert!(
+            CohortMatrix::from_cells(Vec::new(), None, 12)
+                .cohorts
+                .is_empty()
+        );
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct OrderPayment {
+    pub order_id: String,
+    pub payment_sequential: i32,
+    pub payment_type: String,
+    pub payment_installments: i32,
+    pub payment_value: f64,
+}
+
+/// Segments assigned by the RFM job, from best to worst.
+pub const RFM_SEGMENTS: &[&str] = &[
+    "champions",
+    "loyal",
+    "new_customers",
+    "promising",
+    "at_risk",
+    "cant_lose",
+    "hibernating",
+    "lost",
+];
+
+/// Segment of a person with the given RFM scores, out of [`RFM_SEGMENTS`].
+/// Frequency and monetary value are weighed together as their mean.
+pub fn rfm_segment(r_score: i16, f_score: i16, m_score: i16) -> &'static str {
+    // Twice the mean frequency/monetary score, to stay in integers.
+    let fm = f_score + m_score;
+    match r_score {
+        4.. if fm >= 8 => "champions",
+        4.. if fm <= 4 => "new_customers",
+        3.. if fm >= 6 => "loyal",
+        3.. => "promising",
+        2 if fm >= 6 => "at_risk",
+        2 => "hibernating",
+        _ if fm >= 8 => "cant_lose",
+        _ => "lost",
+    }
+}
+
+/// Purchase history of a person, as read by the RFM job.
+#[derive(Debug, Clone, FromRow)]
+pub struct RfmHistory {
+    pub customer_unique_id: String,
+    pub last_purchase_at: chrono::NaiveDateTime,
+    pub recency_days: i32,
+    pub frequency: i32,
+    pub monetary: f64,
+}
+
+/// Scores everyone in `people` against each other. Frequency scores the
+/// order count itself, 5 or more orders scoring 5. Recency and monetary value
+/// score `ceil(5 * share of people at or below the value)`, so equal values
+/// always score the same; people without payments score 1 on monetary value
+/// and are left out of the others' shares.
+pub fn score_rfm(people: Vec<RfmHistory>, computed_at: chrono::NaiveDateTime) -> Vec<CustomerRfm> {
+    let purchases = sorted(people.iter().map(|p| p.last_purchase_at).collect());
+    let payments = sorted(
+        people
+            .iter()
+            .map(|p| p.monetary)
+            .filter(|m| *m > 0.0)
+            .collect(),
+    );
+
+    people
+        .into_iter()
+        .map(|p| {
+            let r_score = cume_dist_score(&purchases, &p.last_purchase_at);
+            let f_score = p.frequency.clamp(1, 5) as i16;
+            let m_score = if p.monetary > 0.0 {
+                cume_dist_score(&payments, &p.monetary)
+            } else {
+                1
+            };
+            CustomerRfm {
+                customer_unique_id: p.customer_unique_id,
+                last_purchase_at: p.last_purchase_at,
+                recency_days: p.recency_days,
+                frequency: p.frequency,
+                monetary: p.monetary,
+                r_score,
+                f_score,
+                m_score,
+                segment: rfm_segment(r_score, f_score, m_score).to_string(),
+                computed_at,
+            }
+        })
+        .collect()
+}
+
+fn sorted<T: PartialOrd>(mut values: Vec<T>) -> Vec<T> {
+    values.sort_by(|a, b| a.partial_cmp(b).expect("RFM inputs are comparable"));
+    values
+}
+
+/// `ceil(5 * cume_dist)` of `value` among `sorted`, which contains it.
+fn cume_dist_score<T: PartialOrd>(sorted: &[T], value: &T) -> i16 {
+    let at_or_below = sorted.partition_point(|v| v <= value);
+    (5 * at_or_below).div_ceil(sorted.len()) as i16
+}
+
+#[cfg(test)]
+mod rfm_tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn person(id: &str, last_purchase_day: u32, frequency: i32, monetary: f64) -> RfmHistory {
+        RfmHistory {
+            customer_unique_id: id.to_string(),
+            last_purchase_at: NaiveDate::from_ymd_opt(2018, 8, last_purchase_day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            recency_days: 31 - last_purchase_day as i32,
+            frequency,
+            monetary,
+        }
+    }
+
+    fn scores(people: Vec<RfmHistory>) -> Vec<(String, i16, i16, i16, String)> {
+        score_rfm(people, NaiveDate::default().and_hms_opt(0, 0, 0).unwrap())
+            .into_iter()
+            .map(|p| {
+                (
+                    p.customer_unique_id,
+                    p.r_score,
+                    p.f_score,
+                    p.m_score,
+                    p.segment,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn equal_values_score_the_same() {
+        // Mostly one-off buyers on the same few days, half without payments,
+        // as in the Olist data.
+        let people = vec![
+            person("a", 1, 1, 0.0),
+            person("b", 1, 1, 0.0),
+            person("c", 1, 1, 0.0),
+            person("d", 1, 1, 0.0),
+            person("e", 1, 1, 0.0),
+            person("f", 20, 1, 50.0),
+            person("g", 20, 1, 50.0),
+            person("h", 20, 2, 50.0),
+            person("i", 31, 3, 120.0),
+            person("j", 31, 7, 900.0),
+        ];
+
+        let expected = [
+            ("a", 3, 1, 1, "promising"),
+            ("b", 3, 1, 1, "promising"),
+            ("c", 3, 1, 1, "promising"),
+            ("d", 3, 1, 1, "promising"),
+            ("e", 3, 1, 1, "promising"),
+            ("f", 4, 1, 3, "new_customers"),
+            ("g", 4, 1, 3, "new_customers"),
+            ("h", 4, 2, 3, "promising"),
+            ("i", 5, 3, 4, "loyal"),
+            ("j", 5, 5, 5, "champions"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(id, r, f, m, segment)| (id.to_string(), *r, *f, *m, segment.to_string()))
+            .collect();
+        assert_eq!(scores(people), expected);
+    }
+
+    #[test]
+    fn scores_do_not_depend_on_input_order() {
+        let people = vec![
+            person("a", 3, 1, 10.0),
+            person("b", 3, 1, 10.0),
+            person("c", 9, 2, 10.0),
+            person("d", 9, 1, 0.0),
+        ];
+        let mut reversed = scores(people.iter().rev().cloned().collect());
+        reversed.reverse();
+        assert_eq!(scores(people), reversed);
+    }
+
+    #[test]
+    fn single_person_scores_top_recency() {
+        assert_eq!(
+            scores(vec![person("a", 5, 1, 0.0)]),
+            [("a".to_string(), 5, 1, 1, "new_customers".to_string())]
+        );
+    }
+
+    #[test]
+    fn every_segment_is_reachable() {
+        let mut reached = std::collections::BTreeSet::new();
+        for r in 1..=5 {
+            for f in 1..=5 {
+                for m in 1..=5 {
+                    let segment = rfm_segment(r, f, m);
+                    assert!(RFM_SEGMENTS.contains(&segment), "unknown {}", segment);
+                    reached.insert(segment);
+                }
+            }
+        }
+        assert_eq!(reached.len(), RFM_SEGMENTS.len());
+    }
+
+    #[test]
+    fn segment_boundaries() {
+        assert_eq!(rfm_segment(4, 4, 4), "champions");
+        assert_eq!(rfm_segment(5, 5, 3), "champions");
+        assert_eq!(rfm_segment(4, 1, 3), "new_customers");
+        assert_eq!(rfm_segment(4, 3, 3), "loyal");
+        assert_eq!(rfm_segment(4, 3, 2), "promising");
+        assert_eq!(rfm_segment(3, 3, 3), "loyal");
+        assert_eq!(rfm_segment(3, 5, 5), "loyal");
+        assert_eq!(rfm_segment(3, 1, 4), "promising");
+        assert_eq!(rfm_segment(2, 3, 3), "at_risk");
+        assert_eq!(rfm_segment(2, 1, 4), "hibernating");
+        assert_eq!(rfm_segment(1, 4, 4), "cant_lose");
+        assert_eq!(rfm_segment(1, 5, 2), "lost");
+    }
+}
+
+/// Recency/frequency/monetary profile of a person. Scores range from 1
+/// (worst) to 5 (best) across everyone scored in the same run, see
+/// [`score_rfm`].
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct CustomerRfm {
+    pub customer_unique_id: String,
+    pub last_purchase_at: chrono::NaiveDateTime,
+    /// Days between the last purchase and the most recent one in the dataset.
+    pub recency_days: i32,
+    pub frequency: i32,
+    /// Sum of payments; 0 when no payment data is loaded.
+    pub monetary: f64,
+    pub r_score: i16,
+    pub f_score: i16,
+    pub m_score: i16,
+    pub segment: String,
+    pub computed_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct RfmSegmentSummary {
+    pub segment: String,
+    pub people: i64,
+    pub avg_recency_days: Option<f64>,
+    pub avg_frequency: Option<f64>,
+    pub avg_monetary: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RfmSegments {
+    pub segments: Vec<RfmSegmentSummary>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RfmRecomputeResult {
+    pub people_scored: u64,
+    pub computed_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FunnelQuery {
+    /// Purchase date range, inclusive.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+/// Orders reaching each stage, every stage implying the previous ones, and
+/// median days spent between consecutive stages.
+#[derive(Debug, FromRow)]
+pub struct FunnelCounts {
+    pub purchased: i64,
+    pub approved: i64,
+    pub handed_to_carrier: i64,
+    pub delivered: i64,
+    pub median_days_to_approval: Option<f64>,
+    pub median_days_to_carrier: Option<f64>,
+    pub median_days_to_delivery: Option<f64>,
+}
+
+impl FunnelCounts {
+    /// Stages in funnel order, each compared with the one before it.
+    pub fn stages(self) -> Vec<FunnelStage> {
+        let reached = [
+            ("purchased", self.purchased, None),
+            ("approved", self.approved, self.median_days_to_approval),
+            (
+                "handed_to_carrier",
+                self.handed_to_carrier,
+                self.median_days_to_carrier,
+            ),
+            ("delivered", self.delivered, self.median_days_to_delivery),
+        ];
+
+        let mut previous: Option<i64> = None;
+        reached
+            .into_iter()
+            .map(|(stage, orders, median_days_from_previous)| {
+                let stage = FunnelStage {
+                    stage,
+                    orders,
+                    drop_off: previous.map_or(0, |p| p - orders),
+                    conversion_rate: previous
+                        .filter(|p| *p > 0)
+                        .map(|p| orders as f64 / p as f64),
+                    median_days_from_previous,
+                };
+                previous = Some(orders);
+                stage
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod funnel_tests {
+    use super::*;
+
+    fn counts(purchased: i64, approved: i64, handed: i64, delivered: i64) -> FunnelCounts {
+        FunnelCounts {
+            purchased,
+            approved,
+            handed_to_carrier: handed,
+            delivered,
+            median_days_to_approval: Some(0.5),
+            median_days_to_carrier: Some(2.0),
+            median_days_to_delivery: Some(8.0),
+        }
+    }
+
+    #[test]
+    fn stages_compare_with_the_previous_stage() {
+        let stages = counts(200, 190, 150, 150).stages();
+
+        let summary: Vec<_> = stages
+            .iter()
+            .map(|s| (s.stage, s.orders, s.drop_off, s.conversion_rate))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("purchased", 200, 0, None),
+                ("approved", 190, 10, Some(0.95)),
+                ("handed_to_carrier", 150, 40, Some(150.0 / 190.0)),
+                ("delivered", 150, 0, Some(1.0)),
+            ]
+        );
+
+        let medians: Vec<_> = stages.iter().map(|s| s.median_days_from_previous).collect();
+        assert_eq!(medians, [None, Some(0.5), Some(2.0), Some(8.0)]);
+    }
+
+    #[test]
+    fn empty_stages_have_no_conversion_rate() {
+        let stages = counts(3, 0, 0, 0).stages();
+        let rates: Vec<_> = stages.iter().map(|s| s.conversion_rate).collect();
+        assert_eq!(rates, [None, Some(0.0), None, None]);
+        assert_eq!(stages[1].drop_off, 3);
+
+        assert!(
+            counts(0, 0, 0, 0)
+                .stages()
+                .iter()
+                .all(|s| s.orders == 0 && s.drop_off == 0 && s.conversion_rate.is_none())
+        );
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FunnelStage {
+    pub stage: &'static str,
+    pub orders: i64,
+    /// Orders that reached the previous stage but not this one.
+    pub drop_off: i64,
+    /// `orders` as a share of the previous stage.
+    pub conversion_rate: Option<f64>,
+    /// Median days from the previous stage to this one.
+    pub median_days_from_previous: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderFunnel {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub stages: Vec<FunnelStage>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct OrderItem {
+    pub order_id: String,
+    pub order_item_id: i32,
+    pub product_id: String,
+    pub seller_id: String,
+    #[serde(deserialize_with = "olist_timestamp::deserialize")]
+    pub shipping_limit_date: chrono::NaiveDateTime,
+    pub price: f64,
+    pub freight_value: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FlowQuery {
+    /// Purchase date range, inclusive.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub format: Option<ExportFormat>,
+}
+
+/// Orders shipped from sellers in one state to customers in another. An
+/// order with sellers in several states counts once per seller state.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct StateFlow {
+    pub seller_state: String,
+    pub customer_state: String,
+    pub orders: i64,
+    pub avg_delivery_days: Option<f64>,
+}
+
+/// Flows as matrices indexed by `seller_states` (rows) and
+/// `customer_states` (columns).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FlowMatrix {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub seller_states: Vec<String>,
+    pub customer_states: Vec<String>,
+    pub orders: Vec<Vec<i64>>,
+    pub avg_delivery_days: Vec<Vec<Option<f64>>>,
+}
+
+impl FlowMatrix {
+    pub fn from_flows(
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+        flows: &[StateFlow],
+    ) -> Self {
+        let mut seller_states: Vec<String> = flows.iter().map(|f| f.seller_state.clone()).collect();
+        seller_states.sort();
+        seller_states.dedup();
+        let mut customer_states: Vec<String> =
+            flows.iter().map(|f| f.customer_state.clone()).collect();
+        customer_states.sort();
+        customer_states.dedup();
+
+        let mut orders = vec![vec![0; customer_states.len()]; seller_states.len()];
+        let mut avg_delivery_days = vec![vec![None; customer_states.len()]; seller_states.len()];
+        for flow in flows {
+            let (Ok(row), Ok(col)) = (
+                seller_states.binary_search(&flow.seller_state),
+                customer_states.binary_search(&flow.customer_state),
+            ) else {
+                continue;
+            };
+            orders[row][col] = flow.orders;
+            avg_delivery_days[row][col] = flow.avg_delivery_days;
+        }
+
+        Self {
+            from,
+            to,
+            seller_states,
+            customer_states,
+            orders,
+            avg_delivery_days,
+        }
+    }
+}
+
+#[cfg(test)]
+mod flow_tests {
+    use super::*;
+
+    fn flow(seller_state: &str, customer_state: &str, orders: i64, days: Option<f64>) -> StateFlow {
+        StateFlow {
+            seller_state: seller_state.to_string(),
+            customer_state: customer_state.to_string(),
+            orders,
+            avg_delivery_days: days,
+        }
+    }
+
+    #[test]
+    fn matrix_is_indexed_by_sorted_states() {
+        let flows = [
+            flow("SP", "RJ", 40, Some(7.5)),
+            flow("MG", "SP", 12, Some(6.0)),
+            flow("SP", "SP", 90, Some(3.2)),
+            flow("MG", "BA", 2, None),
+        ];
+        let matrix = FlowMatrix::from_flows(None, None, &flows);
+
+        assert_eq!(matrix.seller_states, ["MG", "SP"]);
+        assert_eq!(matrix.customer_states, ["BA", "RJ", "SP"]);
+        assert_eq!(matrix.orders, [vec![2, 0, 12], vec![0, 40, 90]]);
+        assert_eq!(
+            matrix.avg_delivery_days,
+            [
+                vec![None, None, Some(6.0)],
+                vec![None, Some(7.5), Some(3.2)]
+            ]
+        );
+    }
+
+    #[test]
+    fn no_flows_is_an_empty_matrix() {
+        let from = chrono::NaiveDate::from_ymd_opt(2018, 1, 1);
+        let matrix = FlowMatrix::from_flows(from, None, &[]);
+        assert_eq!(matrix.from, from);
+        assert!(matrix.seller_states.is_empty());
+        assert!(matrix.customer_states.is_empty());
+        assert!(matrix.orders.is_empty());
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct DatasetRefresh {
+    pub dataset: String,
+    pub last_refreshed_at: chrono::NaiveDateTime,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RefreshReport {
+    pub datasets: Vec<DatasetRefresh>,
+}
+
+/// Analytics payload together with the time its underlying dataset was last
+/// rebuilt.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnalyticsResponse<T> {
+    pub last_refreshed_at: Option<chrono::NaiveDateTime>,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T> AnalyticsResponse<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> AnalyticsResponse<U> {
+        AnalyticsResponse {
+            last_refreshed_at: self.last_refreshed_at,
+            data: f(self.data),
+        }
+    }
+}
+
+/// Product catalog entry. The Olist CSV spells the length columns
+/// `*_lenght`.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Product {
+    pub product_id: String,
+    pub product_category_name: Option<String>,
+    #[serde(alias = "product_name_lenght")]
+    pub product_name_length: Option<i32>,
+    #[serde(alias = "product_description_lenght")]
+    pub product_description_length: Option<i32>,
+    pub product_photos_qty: Option<i32>,
+    pub product_weight_g: Option<i32>,
+    pub product_length_cm: Option<i32>,
+    pub product_height_cm: Option<i32>,
+    pub product_width_cm: Option<i32>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct OrderReview {
+    pub review_id: String,
+    pub order_id: String,
+    pub review_score: i16,
+    pub review_comment_title: Option<String>,
+    pub review_comment_message: Option<String>,
+    #[serde(deserialize_with = "olist_timestamp::deserialize")]
+    pub review_creation_date: chrono::NaiveDateTime,
+    #[serde(default, deserialize_with = "olist_timestamp::option::deserialize")]
+    pub review_answer_timestamp: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SellerRatingQuery {
+    /// Months covered by the trend, ending with the latest review month.
+    #[validate(range(min = 1, max = 24))]
+    #[param(minimum = 1, maximum = 24)]
+    pub months: Option<u32>,
+}
+
+impl SellerRatingQuery {
+    pub fn months(&self) -> u32 {
+        self.months.unwrap_or(6)
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct ScoreCount {
+    pub review_score: i16,
+    pub reviews: i64,
+}
+
+/// Reviews of one month; `avg_score` is `None` for months without any.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct RatingTrendPoint {
+    pub month: String,
+    pub review_count: i64,
+    pub avg_score: Option<f64>,
+}
+
+/// Review scores of the orders a seller took part in. `distribution` maps
+/// each score (1-5) to its review count.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SellerRating {
+    pub seller_id: String,
+    pub review_count: i64,
+    pub avg_score: Option<f64>,
+    #[schema(value_type = std::collections::BTreeMap<String, i64>)]
+    pub distribution: std::collections::BTreeMap<i16, i64>,
+    pub trend: Vec<RatingTrendPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewGroupBy {
+    Seller,
+    Category,
+    State,
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReviewAnalyticsQuery {
+    pub group_by: Option<ReviewGroupBy>,
+    /// Purchase date range, inclusive.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    /// Groups with fewer reviews are left out.
+    #[validate(range(min = 1))]
+    #[param(minimum = 1)]
+    pub min_reviews: Option<i64>,
+    #[validate(range(min = 1, max = 1000))]
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+}
+
+impl ReviewAnalyticsQuery {
+    pub fn min_reviews(&self) -> i64 {
+        self.min_reviews.unwrap_or(1)
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(100)
+    }
+}
+
+/// Review scores of one group. Lateness is measured in days between the
+/// estimated and the actual delivery date; early deliveries are negative.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct ReviewStats {
+    pub key: String,
+    pub review_count: i64,
+    pub avg_score: Option<f64>,
+    pub avg_score_on_time: Option<f64>,
+    pub avg_score_late: Option<f64>,
+    /// Share of delivered orders that arrived after their estimate.
+    pub late_rate: Option<f64>,
+    /// Pearson correlation of score and days late over delivered orders.
+    pub score_lateness_correlation: Option<f64>,
+}
+
+/// Reviews whose order arrived within a band of days late, or was never
+/// delivered.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct LatenessBucket {
+    pub days_late: String,
+    pub review_count: i64,
+    pub avg_score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReviewAnalytics {
+    pub group_by: Option<ReviewGroupBy>,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub overall: ReviewStats,
+    pub by_lateness: Vec<LatenessBucket>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<ReviewStats>>,
+}
+
+/// Payment types reported even when no payment used them.
+pub const PAYMENT_TYPES: &[&str] = &["credit_card", "boleto", "voucher", "debit_card"];
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaymentAnalyticsQuery {
+    /// Purchase date range, inclusive.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct PaymentTotals {
+    pub order_count: i64,
+    pub total_value: f64,
+    /// Orders paid with more than one payment type.
+    pub multi_method_orders: i64,
+}
+
+/// `value_share` is the type's share of the total paid value.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct PaymentTypeStats {
+    pub payment_type: String,
+    pub payments: i64,
+    pub orders: i64,
+    pub total_value: f64,
+    pub avg_value: Option<f64>,
+    pub avg_installments: Option<f64>,
+    #[sqlx(skip)]
+    pub value_share: Option<f64>,
+}
+
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct InstallmentCount {
+    pub installments: i32,
+    pub payments: i64,
+    pub avg_value: Option<f64>,
+    #[sqlx(skip)]
+    pub share: Option<f64>,
+}
+
+/// `avg_ticket` is the average amount paid per order.
+#[derive(Debug, FromRow, Serialize, ToSchema)]
+pub struct StateTicket {
+    pub state: String,
+    pub orders: i64,
+    pub total_value: f64,
+    pub avg_ticket: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PaymentAnalytics {
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub order_count: i64,
+    pub total_value: f64,
+    pub multi_method_orders: i64,
+    pub multi_method_share: Option<f64>,
+    pub by_type: Vec<PaymentTypeStats>,
+    pub installments: Vec<InstallmentCount>,
+    pub by_state: Vec<StateTicket>,
+}
+
+impl PaymentAnalytics {
+    /// Lists the known payment types first, in their usual order and even
+    /// when unused, followed by any others found, and fills in the shares.
+    pub fn new(
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+        totals: PaymentTotals,
+        mut found: Vec<PaymentTypeStats>,
+        mut installments: Vec<InstallmentCount>,
+        by_state: Vec<StateTicket>,
+    ) -> Self {
+        let share = |part: f64, whole: f64| (whole > 0.0).then(|| part / whole);
+
+        let mut by_type: Vec<PaymentTypeStats> = PAYMENT_TYPES
+            .iter()
+            .map(
+                |name| match found.iter().position(|s| s.payment_type == *name) {
+                    Some(i) => found.remove(i),
+                    None => PaymentTypeStats {
+                        payment_type: name.to_string(),
+                        payments: 0,
+                        orders: 0,
+                        total_value: 0.0,
+                        avg_value: None,
+                        avg_installments: None,
+                        value_share: None,
+                    },
+                },
+            )
+            .collect();
+        by_type.append(&mut found);
+        for stats in &mut by_type {
+            stats.value_share = share(stats.total_value, totals.total_value);
+        }
+
+        let payment_count: i64 = installments.iter().map(|i| i.payments).sum();
+        for count in &mut installments {
+            count.share = share(count.payments as f64, payment_count as f64);
+        }
+
+        Self {
+            from,
+            to,
+            order_count: totals.order_count,
+            total_value: totals.total_value,
+            multi_method_orders: totals.multi_method_orders,
+            multi_method_share: share(totals.multi_method_orders as f64, totals.order_count as f64),
+            by_type,
+            installments,
+            by_state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod payment_tests {
+    use super::*;
+
+    fn stats(payment_type: &str, payments: i64, total_value: f64) -> PaymentTypeStats {
+        PaymentTypeStats {
+            payment_type: payment_type.to_string(),
+            payments,
+            orders: payments,
+            total_value,
+            avg_value: Some(total_value / payments as f64),
+            avg_installments: Some(1.0),
+            value_share: None,
+        }
+    }
+
+    fn installments(installments: i32, payments: i64) -> InstallmentCount {
+        InstallmentCount {
+            installments,
+            payments,
+            avg_value: None,
+            share: None,
+        }
+    }
+
+    fn analytics(
+        totals: PaymentTotals,
+        found: Vec<PaymentTypeStats>,
+        counts: Vec<InstallmentCount>,
+    ) -> PaymentAnalytics {
+        PaymentAnalytics::new(None, None, totals, found, counts, Vec::new())
+    }
+
+    #[test]
+    fn shares_are_relative_to_the_totals() {
+        let totals = PaymentTotals {
+            order_count: 8,
+            total_value: 1000.0,
+            multi_method_orders: 2,
+        };
+        let found = vec![
+            stats("boleto", 2, 250.0),
+            stats("credit_card", 5, 700.0),
+            stats("pix", 1, 50.0),
+        ];
+        let counts = vec![installments(1, 6), installments(3, 2)];
+        let analytics = analytics(totals, found, counts);
+
+        let by_type: Vec<_> = analytics
+            .by_type
+            .iter()
+            .map(|s| (s.payment_type.as_str(), s.payments, s.value_share))
+            .collect();
+        assert_eq!(
+            by_type,
+            [
+                ("credit_card", 5, Some(0.7)),
+                ("boleto", 2, Some(0.25)),
+                ("voucher", 0, Some(0.0)),
+                ("debit_card", 0, Some(0.0)),
+                ("pix", 1, Some(0.05)),
+            ]
+        );
+        let shares: Vec<_> = analytics.installments.iter().map(|i| i.share).collect();
+        assert_eq!(shares, [Some(0.75), Some(0.25)]);
+        assert_eq!(analytics.multi_method_share, Some(0.25));
+        assert_eq!(analytics.order_count, 8);
+        assert_eq!(analytics.total_value, 1000.0);
+    }
+
+    #[test]
+    fn empty_range_has_no_shares() {
+        let totals = PaymentTotals {
+            order_count: 0,
+            total_value: 0.0,
+            multi_method_orders: 0,
+        };
+        let analytics = analytics(totals, Vec::new(), Vec::new());
+
+        assert_eq!(analytics.by_type.len(), PAYMENT_TYPES.len());
+        assert!(analytics.by_type.iter().all(|s| s.value_share.is_none()));
+        assert!(analytics.installments.is_empty());
+        assert_eq!(analytics.multi_method_share, None);
+    }
+}
+
+/// Levels of the transit time model, from most to least specific.
+pub const TRANSIT_LEVELS: &[&str] = &[
+    "route_month",
+    "route",
+    "destination_month",
+    "destination",
+    "all",
+];
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryEstimateQuery {
+    #[validate(length(min = 1))]
+    #[param(min_length = 1)]
+    pub seller_id: String,
+    #[validate(length(min = 1))]
+    #[param(min_length = 1)]
+    pub customer_zip: String,
+}
+
+/// Historical duration percentiles, in days, for one level of a model.
+#[derive(Debug, Clone, FromRow)]
+pub struct DurationStats {
+    pub level: String,
+    pub samples: i64,
+    pub p10_days: f64,
+    pub p50_days: f64,
+    pub p90_days: f64,
+}
+
+/// Delivery prediction for an order placed at `purchased_at`. Handling
+/// (purchase to carrier handoff) and transit (handoff to delivery) are
+/// estimated separately; `earliest`/`latest` add up their 10th and 90th
+/// percentiles, an approximate 80% interval.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveryEstimate {
+    pub purchased_at: chrono::NaiveDateTime,
+    pub estimated_delivery_date: chrono::NaiveDate,
+    pub earliest: chrono::NaiveDate,
+    pub latest: chrono::NaiveDate,
+    pub expected_days: f64,
+    pub handling: EstimateBasis,
+    pub transit: EstimateBasis,
+}
+
+/// The model level a component of an estimate came from.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EstimateBasis {
+    pub level: String,
+    pub samples: i64,
+    pub median_days: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SellerDeliveryEstimate {
+    pub seller_id: String,
+    pub seller_state: String,
+    pub customer_zip: String,
+    pub customer_state: String,
+    #[serde(flatten)]
+    pub estimate: DeliveryEstimate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeatmapEntity {
+    Customers,
+    Sellers,
+    Orders,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeatmapLevel {
+    State,
+    City,
+    #[default]
+    Zip,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeatmapQuery {
+    pub entity: HeatmapEntity,
+    pub level: Option<HeatmapLevel>,
+    /// Purchase date range, inclusive; orders only.
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    /// Order status; orders only.
+    pub status: Option<String>,
+}
+
+/// Entities aggregated at one location. Coordinates are the mean of the
+/// entities' zip centroids, `None` when none of them could be located;
+/// `unlocated` counts the entities whose zip has no centroid.
+#[derive(Debug, FromRow)]
+pub struct HeatmapRow {
+    pub zip: Option<String>,
+    pub city: Option<String>,
+    pub state: String,
+    pub count: i64,
+    pub unlocated: i64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub avg_delivery_days: Option<f64>,
+    pub on_time_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PointGeometry {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// `[longitude, latitude]`, as GeoJSON orders them.
+    pub coordinates: [f64; 2],
+}
+
+/// Delivery metrics of the orders at a point.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HeatmapMetrics {
+    pub avg_delivery_days: Option<f64>,
+    pub on_time_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HeatmapProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    pub state: String,
+    pub count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<HeatmapMetrics>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HeatmapFeature {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub geometry: PointGeometry,
+    pub properties: HeatmapProperties,
+}
+
+/// GeoJSON FeatureCollection; `unlocated` counts entities whose zip code
+/// prefix has no centroid. At city and state level such entities still count
+/// towards their place's point when others there could be located.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Heatmap {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub entity: HeatmapEntity,
+    pub level: HeatmapLevel,
+    pub unlocated: i64,
+    pub features: Vec<HeatmapFeature>,
+}
+
+impl Heatmap {
+    pub fn from_rows(entity: HeatmapEntity, level: HeatmapLevel, rows: Vec<HeatmapRow>) -> Self {
+        let unlocated = rows.iter().map(|row| row.unlocated).sum();
+        let features = rows
+            .into_iter()
+            .filter_map(|row| {
+                let (Some(latitude), Some(longitude)) = (row.latitude, row.longitude) else {
+                    return None;
+                };
+                Some(HeatmapFeature {
+                    kind: "Feature",
+                    geometry: PointGeometry {
+                        kind: "Point",
+                        coordinates: [longitude, latitude],
+                    },
+                    properties: HeatmapProperties {
+                        zip: row.zip,
+                        city: row.city,
+                        state: row.state,
+                        count: row.count,
+                        metrics: (entity == HeatmapEntity::Orders).then_some(HeatmapMetrics {
+                            avg_delivery_days: row.avg_delivery_days,
+                            on_time_rate: row.on_time_rate,
+                        }),
+                    },
+                })
+            })
+            .collect();
+
+        Self {
+            kind: "FeatureCollection",
+            entity,
+            level,
+            unlocated,
+            features,
+        }
+    }
+}
+
+#[cfg(test)]
+mod heatmap_tests {
+    use super::*;
+
+    fn row(city: &str, count: i64, unlocated: i64, at: Option<(f64, f64)>) -> HeatmapRow {
+        HeatmapRow {
+            zip: None,
+            city: Some(city.to_string()),
+            state: "SP".to_string(),
+            count,
+            unlocated,
+            latitude: at.map(|(lat, _)| lat),
+            longitude: at.map(|(_, lon)| lon),
+            avg_delivery_days: Some(9.5),
+            on_time_rate: Some(0.9),
+        }
+    }
+
+    #[test]
+    fn unlocated_counts_every_entity_without_a_centroid() {
+        let rows = vec![
+            row("campinas", 10, 3, Some((-22.9, -47.06))),
+            row("nowhere", 4, 4, None),
+            row("santos", 6, 0, Some((-23.96, -46.33))),
+        ];
+        let heatmap = Heatmap::from_rows(HeatmapEntity::Customers, HeatmapLevel::City, rows);
+
+        assert_eq!(heatmap.unlocated, 7);
+        let points: Vec<_> = heatmap
+            .features
+            .iter()
+            .map(|f| {
+                (
+                    f.properties.city.as_deref(),
+                    f.properties.count,
+                    f.geometry.coordinates,
+                )
+            })
+            .collect();
+        assert_eq!(
+            points,
+            [
+                (Some("campinas"), 10, [-47.06, -22.9]),
+                (Some("santos"), 6, [-46.33, -23.96]),
+            ]
+        );
+        assert!(
+            heatmap
+                .features
+                .iter()
+                .all(|f| f.properties.metrics.is_none())
+        );
+    }
+
+    #[test]
+    fn order_points_carry_delivery_metrics() {
+        let rows = vec![row("campinas", 2, 0, Some((-22.9, -47.06)))];
+        let heatmap = Heatmap::from_rows(HeatmapEntity::Orders, HeatmapLevel::City, rows);
+
+        let metrics = heatmap.features[0].properties.metrics.as_ref().unwrap();
+        assert_eq!(metrics.avg_delivery_days, Some(9.5));
+        assert_eq!(metrics.on_time_rate, Some(0.9));
+        assert_eq!(heatmap.unlocated, 0);
+    }
 }
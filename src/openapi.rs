@@ -0,0 +1,60 @@
+use utoipa::OpenApi;
+
+use crate::handlers;
+
+/// OpenAPI document of the HTTP API, served at `/openapi.json`. Schemas are
+/// collected from the handlers' request and response types.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Brazilian E-Commerce API",
+        description = "Customers, sellers, orders and analytics over the Olist dataset."
+    ),
+    servers((url = "/v1", description = "Current version")),
+    paths(
+        handlers::create_customer_handler,
+        handlers::get_customers_handler,
+        handlers::lookup_customers_handler,
+        handlers::get_customer_by_id_handler,
+        handlers::update_customer_handler,
+        handlers::delete_customer_handler,
+        handlers::get_customer_orders_handler,
+        handlers::get_customer_nearby_sellers_handler,
+        handlers::create_seller_handler,
+        handlers::get_sellers_handler,
+        handlers::lookup_sellers_handler,
+        handlers::get_nearby_sellers_handler,
+        handlers::get_seller_by_id_handler,
+        handlers::get_seller_rating_handler,
+        handlers::create_order_handler,
+        handlers::get_orders_handler,
+        handlers::lookup_orders_handler,
+        handlers::get_order_by_id_handler,
+        handlers::search_handler,
+        handlers::heatmap_handler,
+        handlers::delivery_estimate_handler,
+        handlers::delivery_analytics_handler,
+        handlers::order_timeseries_handler,
+        handlers::cohorts_handler,
+        handlers::funnel_handler,
+        handlers::flows_handler,
+        handlers::reviews_analytics_handler,
+        handlers::payments_analytics_handler,
+        handlers::rfm_segments_handler,
+        handlers::person_rfm_handler,
+        handlers::recompute_rfm_handler,
+        handlers::refresh_analytics_handler,
+        handlers::load_data_from_csv_handler,
+    ),
+    tags(
+        (name = "customers"),
+        (name = "sellers"),
+        (name = "orders"),
+        (name = "search"),
+        (name = "geo"),
+        (name = "estimates"),
+        (name = "analytics"),
+        (name = "admin", description = "Data loading and analytics maintenance"),
+    )
+)]
+pub struct ApiDoc;
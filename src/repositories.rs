@@ -1,20 +1,383 @@
+use crate::filter::{FilterExpr, FilterOp, Predicate};
 use crate::models::{
-    CreateCustomerDto, CreateOrderDto, CreateSellerDto, Customer, CustomerFilter, Order,
-    OrderFilter, PaginationParams, Seller, SellerFilter, UpdateCustomerDto,
+    BoundingBox, CohortCell, CountMode, CreateCustomerDto, CreateOrderDto, CreateSellerDto,
+    CursorDirection, Customer, CustomerFilter, CustomerRfm, DatasetRefresh, DateRange,
+    DeliveryGroupBy, DeliveryStats, DurationStats, FieldMeta, FieldSet, FilterMatch, FunnelCounts,
+    HeatmapEntity, HeatmapLevel, HeatmapRow, InstallmentCount, KeysetParams, LatenessBucket,
+    LocationFilter, NearbySeller, Order, OrderFilter, OrderItem, OrderPayment, OrderReview,
+    PaginationParams, PaymentTotals, PaymentTypeStats, Product, RatingTrendPoint, ReviewGroupBy,
+    ReviewStats, RfmHistory, RfmSegmentSummary, RowCount, ScoreCount, SearchHit, Seller,
+    SellerFilter, SortSpec, SqlValue, StateFlow, StateTicket, TimeseriesInterval, TimeseriesRow,
+    UpdateCustomerDto, ZipCentroid,
 };
 use async_trait::async_trait;
-use sqlx::{PgPool, Result as SqlxResult};
+use sqlx::{PgPool, Postgres, QueryBuilder, Result as SqlxResult};
 use tracing::{error, info, instrument};
 
+fn push_value(qb: &mut QueryBuilder<'_, Postgres>, value: &SqlValue) {
+    match value {
+        SqlValue::Text(v) => qb.push_bind(v.clone()),
+        SqlValue::Timestamp(v) => qb.push_bind(*v),
+    };
+}
+
+/// Matches `{prefix}_city_normalized` (lowercased, unaccented) against the
+/// input normalized the same way.
+fn push_city_match(qb: &mut QueryBuilder<'_, Postgres>, prefix: &str, city: &str) {
+    qb.push(format!(
+        " AND {}_city_normalized = lower(immutable_unaccent(",
+        prefix
+    ))
+    .push_bind(city.trim().to_string())
+    .push("))");
+}
+
+fn push_city_prefix_match(qb: &mut QueryBuilder<'_, Postgres>, prefix: &str, city_prefix: &str) {
+    let escaped = city_prefix
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    qb.push(format!(
+        " AND {}_city_normalized LIKE lower(immutable_unaccent(",
+        prefix
+    ))
+    .push_bind(escaped)
+    .push(")) || '%'");
+}
+
+fn push_location_filter(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    filter: &LocationFilter,
+    prefix: &str,
+) {
+    qb.push(" WHERE TRUE");
+    if let Some(city) = &filter.city {
+        push_city_match(qb, prefix, city);
+    }
+    if let Some(city_prefix) = &filter.city_prefix {
+        push_city_prefix_match(qb, prefix, city_prefix);
+    }
+    if let Some(state) = &filter.state {
+        qb.push(format!(" AND {}_state = ", prefix))
+            .push_bind(state.clone());
+    }
+    if let Some(expr) = &filter.expr {
+        qb.push(" AND ");
+        push_filter_expr(qb, expr);
+    }
+}
+
+fn push_filter_column(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    field: &FieldMeta,
+    matching: Option<FilterMatch>,
+) {
+    match matching {
+        Some(FilterMatch::Normalized(column)) => qb.push(column),
+        _ => qb.push(field.name),
+    };
+}
+
+/// Binds a filter value, normalized like the column it is compared with.
+fn push_filter_value(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    value: &SqlValue,
+    matching: Option<FilterMatch>,
+) {
+    match matching {
+        Some(FilterMatch::Normalized(_)) => {
+            qb.push("lower(immutable_unaccent(");
+            push_value(qb, value);
+            qb.push("))");
+        }
+        Some(FilterMatch::Uppercase) => {
+            qb.push("upper(");
+            push_value(qb, value);
+            qb.push(")");
+        }
+        None => push_value(qb, value),
+    }
+}
+
+/// Compiles a validated `?filter=` expression. Column names come from the
+/// resource's field metadata; every value is bound.
+fn push_filter_expr(qb: &mut QueryBuilder<'_, Postgres>, expr: &FilterExpr) {
+    match expr {
+        FilterExpr::And(items) | FilterExpr::Or(items) => {
+            let joiner = if matches!(expr, FilterExpr::And(_)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            qb.push("(");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    qb.push(joiner);
+                }
+                push_filter_expr(qb, item);
+            }
+            qb.push(")");
+        }
+        FilterExpr::Predicate(Predicate::Compare {
+            field,
+            matching,
+            op,
+            value,
+        }) => {
+            let operator = match op {
+                FilterOp::Eq => " = ",
+                FilterOp::Ne => " <> ",
+                FilterOp::Lt => " < ",
+                FilterOp::Le => " <= ",
+                FilterOp::Gt => " > ",
+                FilterOp::Ge => " >= ",
+                FilterOp::In | FilterOp::Out | FilterOp::Null => {
+                    unreachable!("list and null operators are parsed into their own predicates")
+                }
+            };
+            push_filter_column(qb, field, *matching);
+            qb.push(operator);
+            push_filter_value(qb, value, *matching);
+        }
+        FilterExpr::Predicate(Predicate::Like {
+            field,
+            matching,
+            negated,
+            pattern,
+        }) => {
+            push_filter_column(qb, field, *matching);
+            qb.push(if *negated { " NOT LIKE " } else { " LIKE " });
+            push_filter_value(qb, &SqlValue::Text(pattern.clone()), *matching);
+        }
+        FilterExpr::Predicate(Predicate::List {
+            field,
+            matching,
+            negated,
+            values,
+        }) => {
+            push_filter_column(qb, field, *matching);
+            qb.push(if *negated { " NOT IN (" } else { " IN (" });
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    qb.push(", ");
+                }
+                push_filter_value(qb, value, *matching);
+            }
+            qb.push(")");
+        }
+        FilterExpr::Predicate(Predicate::Null { field, is_null }) => {
+            qb.push(field.name)
+                .push(if *is_null { " IS NULL" } else { " IS NOT NULL" });
+        }
+    }
+}
+
+fn push_date_range(qb: &mut QueryBuilder<'_, Postgres>, column: &str, range: &DateRange) {
+    if let Some(start) = range.start() {
+        qb.push(format!(" AND {} >= ", column)).push_bind(start);
+    }
+    if let Some(end) = range.end() {
+        qb.push(format!(" AND {} < ", column)).push_bind(end);
+    }
+}
+
+fn push_order_filter(qb: &mut QueryBuilder<'_, Postgres>, filter: &OrderFilter) {
+    qb.push(" WHERE TRUE");
+    if let Some(status) = &filter.order_status {
+        qb.push(" AND order_status = ").push_bind(status.clone());
+    }
+    if !filter.statuses.is_empty() {
+        qb.push(" AND order_status = ANY(")
+            .push_bind(filter.statuses.clone())
+            .push(")");
+    }
+
+    push_date_range(qb, "order_purchase_timestamp", &filter.purchased);
+    push_date_range(qb, "order_approved_at", &filter.approved);
+    push_date_range(qb, "order_delivered_customer_date", &filter.delivered);
+
+    match filter.late {
+        Some(true) => {
+            qb.push(" AND order_delivered_customer_date > order_estimated_delivery_date");
+        }
+        Some(false) => {
+            qb.push(" AND order_delivered_customer_date <= order_estimated_delivery_date");
+        }
+        None => {}
+    }
+    match filter.undelivered {
+        Some(true) => {
+            qb.push(" AND order_delivered_customer_date IS NULL");
+        }
+        Some(false) => {
+            qb.push(" AND order_delivered_customer_date IS NOT NULL");
+        }
+        None => {}
+    }
+
+    if filter.customer_state.is_some() || filter.customer_city.is_some() {
+        qb.push(" AND customer_id IN (SELECT customer_id FROM customers WHERE TRUE");
+        if let Some(state) = &filter.customer_state {
+            qb.push(" AND customer_state = ").push_bind(state.clone());
+        }
+        if let Some(city) = &filter.customer_city {
+            push_city_match(qb, "customer", city);
+        }
+        qb.push(")");
+    }
+
+    if let Some(expr) = &filter.expr {
+        qb.push(" AND ");
+        push_filter_expr(qb, expr);
+    }
+}
+
+fn is_descending(descending: bool, direction: CursorDirection) -> bool {
+    descending != (direction == CursorDirection::Prev)
+}
+
+/// Appends `ORDER BY` for `sort`, reversed when walking a keyset backwards.
+/// Column names come from the resource's field metadata, never from input.
+fn push_order_by(qb: &mut QueryBuilder<'_, Postgres>, sort: &SortSpec, direction: CursorDirection) {
+    qb.push(" ORDER BY ");
+    for (i, key) in sort.keys.iter().enumerate() {
+        if i > 0 {
+            qb.push(", ");
+        }
+        qb.push(key.field.name);
+        qb.push(if is_descending(key.descending, direction) {
+            " DESC"
+        } else {
+            " ASC"
+        });
+    }
+}
+
+/// Appends the predicate selecting rows past `after` in `sort` order,
+/// expanded as `(a > $1) OR (a = $1 AND b > $2) OR ...` so that mixed sort
+/// directions are honoured. The row at `after` itself is selected only when
+/// `inclusive`.
+fn push_keyset_predicate(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    sort: &SortSpec,
+    after: &[SqlValue],
+    direction: CursorDirection,
+    inclusive: bool,
+) {
+    qb.push(" AND (");
+    for (i, key) in sort.keys.iter().enumerate() {
+        if i > 0 {
+            qb.push(" OR ");
+        }
+        qb.push("(");
+        for (prev, value) in sort.keys[..i].iter().zip(after) {
+            qb.push(prev.field.name).push(" = ");
+            push_value(qb, value);
+            qb.push(" AND ");
+        }
+        qb.push(key.field.name);
+        let last = i + 1 == sort.keys.len();
+        qb.push(
+            match (is_descending(key.descending, direction), inclusive && last) {
+                (true, false) => " < ",
+                (true, true) => " <= ",
+                (false, false) => " > ",
+                (false, true) => " >= ",
+            },
+        );
+        push_value(qb, &after[i]);
+        qb.push(")");
+    }
+    qb.push(")");
+}
+
+/// Trims the look-ahead row from a keyset fetch and restores display order.
+fn finish_keyset_page<T>(mut rows: Vec<T>, keyset: &KeysetParams) -> (Vec<T>, bool) {
+    let has_more = rows.len() > keyset.limit as usize;
+    rows.truncate(keyset.limit as usize);
+    if keyset.direction() == CursorDirection::Prev {
+        rows.reverse();
+    }
+    (rows, has_more)
+}
+
+/// Counts the rows matched by `push_filter` on `table` under `mode`.
+/// Estimates come from the planner's row estimate for the filtered scan, so
+/// they are only as fresh as the table statistics.
+async fn count_rows(
+    pool: &PgPool,
+    table: &str,
+    mode: CountMode,
+    push_filter: impl Fn(&mut QueryBuilder<'_, Postgres>),
+) -> SqlxResult<Option<i64>> {
+    match mode {
+        CountMode::Exact => {
+            let mut qb = QueryBuilder::new(format!("SELECT COUNT(*) FROM {}", table));
+            push_filter(&mut qb);
+            let (count,): (i64,) = qb.build_query_as().fetch_one(pool).await?;
+            Ok(Some(count))
+        }
+        CountMode::Estimated => {
+            let mut qb =
+                QueryBuilder::new(format!("EXPLAIN (FORMAT JSON) SELECT 1 FROM {}", table));
+            push_filter(&mut qb);
+            let (plan,): (serde_json::Value,) = qb.build_query_as().fetch_one(pool).await?;
+            let rows = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or(0.0);
+            Ok(Some(rows.round() as i64))
+        }
+        CountMode::None => Ok(None),
+    }
+}
+
+/// Rows to fetch for an offset page: one extra when counting is skipped, to
+/// tell whether a next page exists.
+fn offset_fetch_limit(limit: i64, mode: CountMode) -> i64 {
+    if mode == CountMode::None {
+        limit + 1
+    } else {
+        limit
+    }
+}
+
+fn finish_offset_page<T>(
+    mut rows: Vec<T>,
+    limit: i64,
+    mode: CountMode,
+    total: Option<i64>,
+) -> (Vec<T>, RowCount) {
+    let count = match (mode, total) {
+        (CountMode::Exact, Some(total)) => RowCount::Exact(total),
+        (CountMode::Estimated, Some(total)) => RowCount::Estimated(total),
+        _ => {
+            let has_next = rows.len() > limit as usize;
+            rows.truncate(limit as usize);
+            RowCount::Skipped { has_next }
+        }
+    };
+    (rows, count)
+}
+
 #[async_trait]
 pub trait CustomerRepository: Send + Sync {
     async fn create(&self, dto: CreateCustomerDto) -> SqlxResult<Customer>;
     async fn find_all(
         &self,
         filter: &CustomerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Customer>, i64)>;
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Customer>>;
+    ) -> SqlxResult<(Vec<Customer>, RowCount)>;
+    async fn find_page(
+        &self,
+        filter: &CustomerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Customer>, bool)>;
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Customer>>;
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Customer>>;
     async fn update(&self, id: &str, dto: UpdateCustomerDto) -> SqlxResult<Option<Customer>>;
     async fn delete(&self, id: &str) -> SqlxResult<u64>;
 }
@@ -61,69 +424,109 @@ impl CustomerRepository for PgCustomerRepository {
     async fn find_all(
         &self,
         filter: &CustomerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Customer>, i64)> {
+    ) -> SqlxResult<(Vec<Customer>, RowCount)> {
         let (limit, offset, _, _) = pagination.normalize();
 
-        let count_row: (i64,) = sqlx::query_as(
-            r#"
-            SELECT COUNT(*) FROM customers
-            WHERE ($1::text IS NULL OR customer_city = $1)
-              AND ($2::text IS NULL OR customer_state = $2)
-            "#,
-        )
-        .bind(&filter.city)
-        .bind(&filter.state)
-        .fetch_one(&self.pool)
+        let mode = pagination.count_mode();
+        let total_count = count_rows(&self.pool, "customers", mode, |qb| {
+            push_location_filter(qb, filter, "customer");
+        })
         .await
         .map_err(|e| {
             error!("Error counting customers: {:?}", e);
             e
         })?;
-        let total_count = count_row.0;
 
-        let customers = sqlx::query_as::<_, Customer>(
-            r#"
-            SELECT
-                customer_id, customer_unique_id, customer_zip_code_prefix,
-                customer_city, customer_state
-            FROM customers
-            WHERE ($1::text IS NULL OR customer_city = $1)
-              AND ($2::text IS NULL OR customer_state = $2)
-            ORDER BY customer_zip_code_prefix DESC
-            LIMIT $3 OFFSET $4
-            "#,
-        )
-        .bind(&filter.city)
-        .bind(&filter.state)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| {
-            error!("Error fetching customers: {:?}", e);
-            e
-        })?;
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM customers",
+            fields.columns::<Customer>(&sort.field_names())
+        ));
+        push_location_filter(&mut query, filter, "customer");
+        push_order_by(&mut query, sort, CursorDirection::Next);
+        query
+            .push(" LIMIT ")
+            .push_bind(offset_fetch_limit(limit, mode))
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        let customers = query
+            .build_query_as::<Customer>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching customers: {:?}", e);
+                e
+            })?;
 
-        Ok((customers, total_count))
+        Ok(finish_offset_page(customers, limit, mode, total_count))
     }
 
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Customer>> {
-        sqlx::query_as::<_, Customer>(
-            r#"
-            SELECT
-                customer_id, customer_unique_id, customer_zip_code_prefix,
-                customer_city, customer_state
-            FROM customers WHERE customer_id = $1
-            "#,
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| {
-            error!("Error fetching customer by id: {:?}", e);
-            e
-        })
+    async fn find_page(
+        &self,
+        filter: &CustomerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Customer>, bool)> {
+        let direction = keyset.direction();
+
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM customers",
+            fields.columns::<Customer>(&sort.field_names())
+        ));
+        push_location_filter(&mut query, filter, "customer");
+        if let Some(after) = after {
+            push_keyset_predicate(&mut query, sort, after, direction, keyset.inclusive());
+        }
+        push_order_by(&mut query, sort, direction);
+        query.push(" LIMIT ").push_bind(keyset.limit as i64 + 1);
+
+        let customers = query
+            .build_query_as::<Customer>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching customers page: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_keyset_page(customers, keyset))
+    }
+
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Customer>> {
+        let query = format!(
+            "SELECT {} FROM customers WHERE customer_id = $1",
+            fields.columns::<Customer>(&["customer_id"])
+        );
+
+        sqlx::query_as::<_, Customer>(&query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching customer by id: {:?}", e);
+                e
+            })
+    }
+
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Customer>> {
+        let query = format!(
+            "SELECT {} FROM customers WHERE customer_id = ANY($1)",
+            fields.columns::<Customer>(&["customer_id"])
+        );
+
+        sqlx::query_as::<_, Customer>(&query)
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching customers by ids: {:?}", e);
+                e
+            })
     }
 
     #[instrument(skip(self, dto), fields(customer_id = id))]
@@ -188,9 +591,23 @@ pub trait SellerRepository: Send + Sync {
     async fn find_all(
         &self,
         filter: &SellerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Seller>, i64)>;
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Seller>>;
+    ) -> SqlxResult<(Vec<Seller>, RowCount)>;
+    async fn find_page(
+        &self,
+        filter: &SellerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Seller>, bool)>;
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Seller>>;
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Seller>>;
+    async fn review_score_counts(&self, seller_id: &str) -> SqlxResult<Vec<ScoreCount>>;
+    async fn rating_trend(&self, seller_id: &str, months: i32)
+    -> SqlxResult<Vec<RatingTrendPoint>>;
 }
 
 #[derive(Clone)]
@@ -234,68 +651,189 @@ impl SellerRepository for PgSellerRepository {
     async fn find_all(
         &self,
         filter: &SellerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Seller>, i64)> {
+    ) -> SqlxResult<(Vec<Seller>, RowCount)> {
         let (limit, offset, _, _) = pagination.normalize();
 
-        let count_row: (i64,) = sqlx::query_as(
-            r#"
-            SELECT COUNT(*) FROM sellers
-            WHERE ($1::text IS NULL OR seller_city = $1)
-              AND ($2::text IS NULL OR seller_state = $2)
-            "#,
-        )
-        .bind(&filter.city)
-        .bind(&filter.state)
-        .fetch_one(&self.pool)
+        let mode = pagination.count_mode();
+        let total_count = count_rows(&self.pool, "sellers", mode, |qb| {
+            push_location_filter(qb, filter, "seller");
+        })
         .await
         .map_err(|e| {
             error!("Error counting sellers: {:?}", e);
             e
         })?;
-        let total_count = count_row.0;
 
-        let sellers = sqlx::query_as::<_, Seller>(
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM sellers",
+            fields.columns::<Seller>(&sort.field_names())
+        ));
+        push_location_filter(&mut query, filter, "seller");
+        push_order_by(&mut query, sort, CursorDirection::Next);
+        query
+            .push(" LIMIT ")
+            .push_bind(offset_fetch_limit(limit, mode))
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        let sellers = query
+            .build_query_as::<Seller>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching sellers: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_offset_page(sellers, limit, mode, total_count))
+    }
+
+    async fn find_page(
+        &self,
+        filter: &SellerFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Seller>, bool)> {
+        let direction = keyset.direction();
+
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM sellers",
+            fields.columns::<Seller>(&sort.field_names())
+        ));
+        push_location_filter(&mut query, filter, "seller");
+        if let Some(after) = after {
+            push_keyset_predicate(&mut query, sort, after, direction, keyset.inclusive());
+        }
+        push_order_by(&mut query, sort, direction);
+        query.push(" LIMIT ").push_bind(keyset.limit as i64 + 1);
+
+        let sellers = query
+            .build_query_as::<Seller>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching sellers page: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_keyset_page(sellers, keyset))
+    }
+
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Seller>> {
+        let query = format!(
+            "SELECT {} FROM sellers WHERE seller_id = $1",
+            fields.columns::<Seller>(&["seller_id"])
+        );
+
+        sqlx::query_as::<_, Seller>(&query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching seller by id: {:?}", e);
+                e
+            })
+    }
+
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Seller>> {
+        let query = format!(
+            "SELECT {} FROM sellers WHERE seller_id = ANY($1)",
+            fields.columns::<Seller>(&["seller_id"])
+        );
+
+        sqlx::query_as::<_, Seller>(&query)
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching sellers by ids: {:?}", e);
+                e
+            })
+    }
+
+    /// A review counts once per seller even if the order has several of
+    /// their items.
+    #[instrument(skip(self))]
+    async fn review_score_counts(&self, seller_id: &str) -> SqlxResult<Vec<ScoreCount>> {
+        sqlx::query_as::<_, ScoreCount>(
             r#"
-            SELECT
-                seller_id,
-                seller_zip_code_prefix,
-                seller_city,
-                seller_state
-            FROM sellers
-            WHERE ($1::text IS NULL OR seller_city = $1)
-              AND ($2::text IS NULL OR seller_state = $2)
-            LIMIT $3 OFFSET $4
+            SELECT r.review_score, COUNT(*) AS reviews
+            FROM order_reviews r
+            WHERE EXISTS (
+                SELECT 1 FROM order_items i
+                WHERE i.order_id = r.order_id AND i.seller_id = $1
+            )
+            GROUP BY r.review_score
+            ORDER BY r.review_score
             "#,
         )
-        .bind(&filter.city)
-        .bind(&filter.state)
-        .bind(limit)
-        .bind(offset)
+        .bind(seller_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| {
-            error!("Error fetching sellers: {:?}", e);
+            error!(
+                "Error counting review scores of seller {}: {:?}",
+                seller_id, e
+            );
             e
-        })?;
-
-        Ok((sellers, total_count))
+        })
     }
 
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Seller>> {
-        sqlx::query_as::<_, Seller>(
+    /// Monthly scores over the `months` months up to the latest review in
+    /// the dataset, months without reviews included.
+    #[instrument(skip(self))]
+    async fn rating_trend(
+        &self,
+        seller_id: &str,
+        months: i32,
+    ) -> SqlxResult<Vec<RatingTrendPoint>> {
+        sqlx::query_as::<_, RatingTrendPoint>(
             r#"
+            WITH latest AS (
+                SELECT date_trunc('month', MAX(review_creation_date)) AS month
+                FROM order_reviews
+            ),
+            months AS (
+                SELECT generate_series(
+                    latest.month - make_interval(months => $2 - 1),
+                    latest.month,
+                    interval '1 month'
+                ) AS month
+                FROM latest
+                WHERE latest.month IS NOT NULL
+            ),
+            seller_reviews AS (
+                SELECT r.review_score, date_trunc('month', r.review_creation_date) AS month
+                FROM order_reviews r
+                WHERE EXISTS (
+                    SELECT 1 FROM order_items i
+                    WHERE i.order_id = r.order_id AND i.seller_id = $1
+                )
+            )
             SELECT
-                seller_id, seller_zip_code_prefix,
-                seller_city, seller_state
-            FROM sellers WHERE seller_id = $1
+                to_char(m.month, 'YYYY-MM') AS month,
+                COUNT(sr.review_score) AS review_count,
+                AVG(sr.review_score)::float8 AS avg_score
+            FROM months m
+            LEFT JOIN seller_reviews sr ON sr.month = m.month
+            GROUP BY m.month
+            ORDER BY m.month
             "#,
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(seller_id)
+        .bind(months)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| {
-            error!("Error fetching seller by id: {:?}", e);
+            error!(
+                "Error computing rating trend of seller {}: {:?}",
+                seller_id, e
+            );
             e
         })
     }
@@ -307,14 +845,35 @@ pub trait OrderRepository: Send + Sync {
     async fn find_all(
         &self,
         filter: &OrderFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Order>, i64)>;
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Order>>;
+    ) -> SqlxResult<(Vec<Order>, RowCount)>;
+    async fn find_page(
+        &self,
+        filter: &OrderFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Order>, bool)>;
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Order>>;
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Order>>;
+    async fn find_recent_by_customer_ids(
+        &self,
+        customer_ids: &[String],
+        per_customer: i64,
+    ) -> SqlxResult<Vec<Order>>;
     async fn find_by_customer_id(
         &self,
         customer_id: &str,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Order>, i64)>;
+    ) -> SqlxResult<(Vec<Order>, RowCount)>;
+    async fn upsert_payments(&self, payments: &[OrderPayment]) -> SqlxResult<u64>;
+    async fn upsert_items(&self, items: &[OrderItem]) -> SqlxResult<u64>;
+    async fn upsert_products(&self, products: &[Product]) -> SqlxResult<u64>;
+    async fn upsert_reviews(&self, reviews: &[OrderReview]) -> SqlxResult<u64>;
 }
 
 #[derive(Clone)]
@@ -328,6 +887,12 @@ impl PgOrderRepository {
     }
 }
 
+const PAYMENT_UPSERT_CHUNK: usize = 10_000;
+const ITEM_UPSERT_CHUNK: usize = 10_000;
+const PRODUCT_UPSERT_CHUNK: usize = 10_000;
+const REVIEW_UPSERT_CHUNK: usize = 10_000;
+const RFM_INSERT_CHUNK: usize = 10_000;
+
 #[async_trait]
 impl OrderRepository for PgOrderRepository {
     async fn create(&self, dto: CreateOrderDto) -> SqlxResult<Order> {
@@ -366,52 +931,116 @@ impl OrderRepository for PgOrderRepository {
     async fn find_all(
         &self,
         filter: &OrderFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Order>, i64)> {
+    ) -> SqlxResult<(Vec<Order>, RowCount)> {
         let (limit, offset, _, _) = pagination.normalize();
 
-        let count_row: (i64,) = sqlx::query_as(
-            r#"
-            SELECT COUNT(*) FROM orders
-            WHERE ($1::text IS NULL OR order_status = $1)
-            "#,
-        )
-        .bind(&filter.order_status)
-        .fetch_one(&self.pool)
+        let mode = pagination.count_mode();
+        let total_count = count_rows(&self.pool, "orders", mode, |qb| {
+            push_order_filter(qb, filter);
+        })
         .await
         .map_err(|e| {
             tracing::error!("Error counting orders: {:?}", e);
             e
         })?;
-        let total_count = count_row.0;
 
-        let orders = sqlx::query_as::<_, Order>(
-            r#"
-            SELECT
-                order_id, customer_id, order_status,
-                order_purchase_timestamp, order_approved_at,
-                order_delivered_carrier_date, order_delivered_customer_date,
-                order_estimated_delivery_date
-            FROM orders
-            WHERE ($1::text IS NULL OR order_status = $1)
-            ORDER BY order_purchase_timestamp DESC
-            LIMIT $2 OFFSET $3
-            "#,
-        )
-        .bind(&filter.order_status)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Error fetching orders: {:?}", e);
-            e
-        })?;
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM orders",
+            fields.columns::<Order>(&sort.field_names())
+        ));
+        push_order_filter(&mut query, filter);
+        push_order_by(&mut query, sort, CursorDirection::Next);
+        query
+            .push(" LIMIT ")
+            .push_bind(offset_fetch_limit(limit, mode))
+            .push(" OFFSET ")
+            .push_bind(offset);
+
+        let orders = query
+            .build_query_as::<Order>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching orders: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_offset_page(orders, limit, mode, total_count))
+    }
+
+    async fn find_page(
+        &self,
+        filter: &OrderFilter,
+        sort: &SortSpec,
+        fields: &FieldSet,
+        keyset: &KeysetParams,
+        after: Option<&[SqlValue]>,
+    ) -> SqlxResult<(Vec<Order>, bool)> {
+        let direction = keyset.direction();
+
+        let mut query = QueryBuilder::new(format!(
+            "SELECT {} FROM orders",
+            fields.columns::<Order>(&sort.field_names())
+        ));
+        push_order_filter(&mut query, filter);
+        if let Some(after) = after {
+            push_keyset_predicate(&mut query, sort, after, direction, keyset.inclusive());
+        }
+        push_order_by(&mut query, sort, direction);
+        query.push(" LIMIT ").push_bind(keyset.limit as i64 + 1);
 
-        Ok((orders, total_count))
+        let orders = query
+            .build_query_as::<Order>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching orders page: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_keyset_page(orders, keyset))
+    }
+
+    async fn find_by_id(&self, id: &str, fields: &FieldSet) -> SqlxResult<Option<Order>> {
+        let query = format!(
+            "SELECT {} FROM orders WHERE order_id = $1",
+            fields.columns::<Order>(&["order_id"])
+        );
+
+        sqlx::query_as::<_, Order>(&query)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching order by id: {:?}", e);
+                e
+            })
     }
 
-    async fn find_by_id(&self, id: &str) -> SqlxResult<Option<Order>> {
+    async fn find_by_ids(&self, ids: &[String], fields: &FieldSet) -> SqlxResult<Vec<Order>> {
+        let query = format!(
+            "SELECT {} FROM orders WHERE order_id = ANY($1)",
+            fields.columns::<Order>(&["order_id"])
+        );
+
+        sqlx::query_as::<_, Order>(&query)
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching orders by ids: {:?}", e);
+                e
+            })
+    }
+
+    async fn find_recent_by_customer_ids(
+        &self,
+        customer_ids: &[String],
+        per_customer: i64,
+    ) -> SqlxResult<Vec<Order>> {
         sqlx::query_as::<_, Order>(
             r#"
             SELECT
@@ -419,14 +1048,26 @@ impl OrderRepository for PgOrderRepository {
                 order_purchase_timestamp, order_approved_at,
                 order_delivered_carrier_date, order_delivered_customer_date,
                 order_estimated_delivery_date
-            FROM orders WHERE order_id = $1
+            FROM (
+                SELECT
+                    *,
+                    ROW_NUMBER() OVER (
+                        PARTITION BY customer_id
+                        ORDER BY order_purchase_timestamp DESC, order_id DESC
+                    ) AS position
+                FROM orders
+                WHERE customer_id = ANY($1)
+            ) ranked
+            WHERE position <= $2
+            ORDER BY customer_id, order_purchase_timestamp DESC, order_id DESC
             "#,
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(customer_ids)
+        .bind(per_customer)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| {
-            tracing::error!("Error fetching order by id: {:?}", e);
+            error!("Error fetching orders for customers: {:?}", e);
             e
         })
     }
@@ -434,48 +1075,1566 @@ impl OrderRepository for PgOrderRepository {
     async fn find_by_customer_id(
         &self,
         customer_id: &str,
+        fields: &FieldSet,
         pagination: &PaginationParams,
-    ) -> SqlxResult<(Vec<Order>, i64)> {
+    ) -> SqlxResult<(Vec<Order>, RowCount)> {
         let (limit, offset, _, _) = pagination.normalize();
 
-        let count_row: (i64,) = sqlx::query_as(
-            r#"
-            SELECT COUNT(*) FROM orders
-            WHERE customer_id = $1
-            "#,
-        )
-        .bind(customer_id)
-        .fetch_one(&self.pool)
+        let mode = pagination.count_mode();
+        let total_count = count_rows(&self.pool, "orders", mode, |qb| {
+            qb.push(" WHERE customer_id = ")
+                .push_bind(customer_id.to_string());
+        })
         .await
         .map_err(|e| {
             error!("Error counting orders for customer: {:?}", e);
             e
         })?;
-        let total_count = count_row.0;
 
-        let orders = sqlx::query_as::<_, Order>(
+        let query = format!(
             r#"
-            SELECT
-                order_id, customer_id, order_status,
-                order_purchase_timestamp, order_approved_at,
-                order_delivered_carrier_date, order_delivered_customer_date,
-                order_estimated_delivery_date
+            SELECT {}
             FROM orders
             WHERE customer_id = $1
             ORDER BY order_purchase_timestamp DESC
             LIMIT $2 OFFSET $3
             "#,
-        )
-        .bind(customer_id)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| {
-            error!("Error fetching orders for customer: {:?}", e);
-            e
-        })?;
+            fields.columns::<Order>(&["order_id"])
+        );
+
+        let orders = sqlx::query_as::<_, Order>(&query)
+            .bind(customer_id)
+            .bind(offset_fetch_limit(limit, mode))
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error fetching orders for customer: {:?}", e);
+                e
+            })?;
+
+        Ok(finish_offset_page(orders, limit, mode, total_count))
+    }
 
-        Ok((orders, total_count))
+    /// Payments referencing unknown orders are skipped.
+    #[instrument(skip(self, payments), fields(count = payments.len()))]
+    async fn upsert_payments(&self, payments: &[OrderPayment]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut affected = 0;
+
+        for chunk in payments.chunks(PAYMENT_UPSERT_CHUNK) {
+            let order_ids: Vec<&str> = chunk.iter().map(|p| p.order_id.as_str()).collect();
+            let sequentials: Vec<i32> = chunk.iter().map(|p| p.payment_sequential).collect();
+            let types: Vec<&str> = chunk.iter().map(|p| p.payment_type.as_str()).collect();
+            let installments: Vec<i32> = chunk.iter().map(|p| p.payment_installments).collect();
+            let values: Vec<f64> = chunk.iter().map(|p| p.payment_value).collect();
+
+            affected += sqlx::query(
+                r#"
+                INSERT INTO order_payments (
+                    order_id, payment_sequential, payment_type,
+                    payment_installments, payment_value
+                )
+                SELECT p.*
+                FROM UNNEST($1::text[], $2::int[], $3::text[], $4::int[], $5::float8[])
+                    AS p(order_id, payment_sequential, payment_type, payment_installments, payment_value)
+                WHERE EXISTS (SELECT 1 FROM orders o WHERE o.order_id = p.order_id)
+                ON CONFLICT (order_id, payment_sequential) DO UPDATE SET
+                    payment_type = EXCLUDED.payment_type,
+                    payment_installments = EXCLUDED.payment_installments,
+                    payment_value = EXCLUDED.payment_value
+                "#,
+            )
+            .bind(order_ids)
+            .bind(sequentials)
+            .bind(types)
+            .bind(installments)
+            .bind(values)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Error upserting order payments: {:?}", e);
+                e
+            })?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Upserted {} order payments", affected);
+
+        Ok(affected)
+    }
+
+    /// Items referencing unknown orders or sellers are skipped.
+    #[instrument(skip(self, items), fields(count = items.len()))]
+    async fn upsert_items(&self, items: &[OrderItem]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut affected = 0;
+
+        for chunk in items.chunks(ITEM_UPSERT_CHUNK) {
+            let order_ids: Vec<&str> = chunk.iter().map(|i| i.order_id.as_str()).collect();
+            let item_ids: Vec<i32> = chunk.iter().map(|i| i.order_item_id).collect();
+            let product_ids: Vec<&str> = chunk.iter().map(|i| i.product_id.as_str()).collect();
+            let seller_ids: Vec<&str> = chunk.iter().map(|i| i.seller_id.as_str()).collect();
+            let shipping_limits: Vec<chrono::NaiveDateTime> =
+                chunk.iter().map(|i| i.shipping_limit_date).collect();
+            let prices: Vec<f64> = chunk.iter().map(|i| i.price).collect();
+            let freights: Vec<f64> = chunk.iter().map(|i| i.freight_value).collect();
+
+            affected += sqlx::query(
+                r#"
+                INSERT INTO order_items (
+                    order_id, order_item_id, product_id, seller_id,
+                    shipping_limit_date, price, freight_value
+                )
+                SELECT i.*
+                FROM UNNEST(
+                    $1::text[], $2::int[], $3::text[], $4::text[],
+                    $5::timestamp[], $6::float8[], $7::float8[]
+                ) AS i(
+                    order_id, order_item_id, product_id, seller_id,
+                    shipping_limit_date, price, freight_value
+                )
+                WHERE EXISTS (SELECT 1 FROM orders o WHERE o.order_id = i.order_id)
+                  AND EXISTS (SELECT 1 FROM sellers s WHERE s.seller_id = i.seller_id)
+                ON CONFLICT (order_id, order_item_id) DO UPDATE SET
+                    product_id = EXCLUDED.product_id,
+                    seller_id = EXCLUDED.seller_id,
+                    shipping_limit_date = EXCLUDED.shipping_limit_date,
+                    price = EXCLUDED.price,
+                    freight_value = EXCLUDED.freight_value
+                "#,
+            )
+            .bind(order_ids)
+            .bind(item_ids)
+            .bind(product_ids)
+            .bind(seller_ids)
+            .bind(shipping_limits)
+            .bind(prices)
+            .bind(freights)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Error upserting order items: {:?}", e);
+                e
+            })?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Upserted {} order items", affected);
+
+        Ok(affected)
+    }
+
+    #[instrument(skip(self, products), fields(count = products.len()))]
+    async fn upsert_products(&self, products: &[Product]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut affected = 0;
+
+        for chunk in products.chunks(PRODUCT_UPSERT_CHUNK) {
+            let ids: Vec<&str> = chunk.iter().map(|p| p.product_id.as_str()).collect();
+            let categories: Vec<Option<&str>> = chunk
+                .iter()
+                .map(|p| p.product_category_name.as_deref())
+                .collect();
+            let name_lengths: Vec<Option<i32>> =
+                chunk.iter().map(|p| p.product_name_length).collect();
+            let description_lengths: Vec<Option<i32>> =
+                chunk.iter().map(|p| p.product_description_length).collect();
+            let photos: Vec<Option<i32>> = chunk.iter().map(|p| p.product_photos_qty).collect();
+            let weights: Vec<Option<i32>> = chunk.iter().map(|p| p.product_weight_g).collect();
+            let lengths: Vec<Option<i32>> = chunk.iter().map(|p| p.product_length_cm).collect();
+            let heights: Vec<Option<i32>> = chunk.iter().map(|p| p.product_height_cm).collect();
+            let widths: Vec<Option<i32>> = chunk.iter().map(|p| p.product_width_cm).collect();
+
+            affected += sqlx::query(
+                r#"
+                INSERT INTO products (
+                    product_id, product_category_name, product_name_length,
+                    product_description_length, product_photos_qty, product_weight_g,
+                    product_length_cm, product_height_cm, product_width_cm
+                )
+                SELECT *
+                FROM UNNEST(
+                    $1::text[], $2::text[], $3::int[], $4::int[], $5::int[],
+                    $6::int[], $7::int[], $8::int[], $9::int[]
+                )
+                ON CONFLICT (product_id) DO UPDATE SET
+                    product_category_name = EXCLUDED.product_category_name,
+                    product_name_length = EXCLUDED.product_name_length,
+                    product_description_length = EXCLUDED.product_description_length,
+                    product_photos_qty = EXCLUDED.product_photos_qty,
+                    product_weight_g = EXCLUDED.product_weight_g,
+                    product_length_cm = EXCLUDED.product_length_cm,
+                    product_height_cm = EXCLUDED.product_height_cm,
+                    product_width_cm = EXCLUDED.product_width_cm
+                "#,
+            )
+            .bind(ids)
+            .bind(categories)
+            .bind(name_lengths)
+            .bind(description_lengths)
+            .bind(photos)
+            .bind(weights)
+            .bind(lengths)
+            .bind(heights)
+            .bind(widths)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Error upserting products: {:?}", e);
+                e
+            })?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Upserted {} products", affected);
+
+        Ok(affected)
+    }
+
+    /// Reviews referencing unknown orders are skipped.
+    #[instrument(skip(self, reviews), fields(count = reviews.len()))]
+    async fn upsert_reviews(&self, reviews: &[OrderReview]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut affected = 0;
+
+        for chunk in reviews.chunks(REVIEW_UPSERT_CHUNK) {
+            let review_ids: Vec<&str> = chunk.iter().map(|r| r.review_id.as_str()).collect();
+            let order_ids: Vec<&str> = chunk.iter().map(|r| r.order_id.as_str()).collect();
+            let scores: Vec<i16> = chunk.iter().map(|r| r.review_score).collect();
+            let titles: Vec<Option<&str>> = chunk
+                .iter()
+                .map(|r| r.review_comment_title.as_deref())
+                .collect();
+            let messages: Vec<Option<&str>> = chunk
+                .iter()
+                .map(|r| r.review_comment_message.as_deref())
+                .collect();
+            let created: Vec<chrono::NaiveDateTime> =
+                chunk.iter().map(|r| r.review_creation_date).collect();
+            let answered: Vec<Option<chrono::NaiveDateTime>> =
+                chunk.iter().map(|r| r.review_answer_timestamp).collect();
+
+            affected += sqlx::query(
+                r#"
+                INSERT INTO order_reviews (
+                    review_id, order_id, review_score, review_comment_title,
+                    review_comment_message, review_creation_date, review_answer_timestamp
+                )
+                SELECT r.*
+                FROM UNNEST(
+                    $1::text[], $2::text[], $3::int2[], $4::text[],
+                    $5::text[], $6::timestamp[], $7::timestamp[]
+                ) AS r(
+                    review_id, order_id, review_score, review_comment_title,
+                    review_comment_message, review_creation_date, review_answer_timestamp
+                )
+                WHERE EXISTS (SELECT 1 FROM orders o WHERE o.order_id = r.order_id)
+                ON CONFLICT (review_id, order_id) DO UPDATE SET
+                    review_score = EXCLUDED.review_score,
+                    review_comment_title = EXCLUDED.review_comment_title,
+                    review_comment_message = EXCLUDED.review_comment_message,
+                    review_creation_date = EXCLUDED.review_creation_date,
+                    review_answer_timestamp = EXCLUDED.review_answer_timestamp
+                "#,
+            )
+            .bind(review_ids)
+            .bind(order_ids)
+            .bind(scores)
+            .bind(titles)
+            .bind(messages)
+            .bind(created)
+            .bind(answered)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Error upserting order reviews: {:?}", e);
+                e
+            })?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Upserted {} order reviews", affected);
+
+        Ok(affected)
+    }
+}
+
+#[async_trait]
+pub trait SearchRepository: Send + Sync {
+    async fn search(&self, term: &str, limit: i64) -> SqlxResult<Vec<SearchHit>>;
+}
+
+#[derive(Clone)]
+pub struct PgSearchRepository {
+    pool: PgPool,
+}
+
+impl PgSearchRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Minimum `word_similarity` for a hit. Lower than the pg_trgm default (0.6)
+/// so that misspelled city names still match.
+const SEARCH_SIMILARITY_THRESHOLD: &str = "0.3";
+
+#[async_trait]
+impl SearchRepository for PgSearchRepository {
+    #[instrument(skip(self))]
+    async fn search(&self, term: &str, limit: i64) -> SqlxResult<Vec<SearchHit>> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)")
+            .bind(SEARCH_SIMILARITY_THRESHOLD)
+            .execute(&mut *tx)
+            .await?;
+
+        // Each branch uses the `<%` operator so that the trigram indexes apply;
+        // a record matching on several fields is reported once, on its best one.
+        let hits = sqlx::query_as::<_, SearchHit>(
+            r#"
+            WITH candidates AS (
+                SELECT 'customer' AS kind, customer_id AS id, 'customer_id' AS matched_field,
+                       customer_id AS matched_value, word_similarity($1, customer_id) AS score
+                FROM customers WHERE $1 <% customer_id
+                UNION ALL
+                SELECT 'customer', customer_id, 'customer_unique_id',
+                       customer_unique_id, word_similarity($1, customer_unique_id)
+                FROM customers WHERE $1 <% customer_unique_id
+                UNION ALL
+                SELECT 'customer', customer_id, 'customer_city',
+                       customer_city, word_similarity(lower(immutable_unaccent($1)), customer_city_normalized)
+                FROM customers WHERE lower(immutable_unaccent($1)) <% customer_city_normalized
+                UNION ALL
+                SELECT 'customer', customer_id, 'customer_zip_code_prefix',
+                       customer_zip_code_prefix, word_similarity($1, customer_zip_code_prefix)
+                FROM customers WHERE $1 <% customer_zip_code_prefix
+                UNION ALL
+                SELECT 'seller', seller_id, 'seller_id',
+                       seller_id, word_similarity($1, seller_id)
+                FROM sellers WHERE $1 <% seller_id
+                UNION ALL
+                SELECT 'seller', seller_id, 'seller_city',
+                       seller_city, word_similarity(lower(immutable_unaccent($1)), seller_city_normalized)
+                FROM sellers WHERE lower(immutable_unaccent($1)) <% seller_city_normalized
+                UNION ALL
+                SELECT 'seller', seller_id, 'seller_zip_code_prefix',
+                       seller_zip_code_prefix, word_similarity($1, seller_zip_code_prefix)
+                FROM sellers WHERE $1 <% seller_zip_code_prefix
+                UNION ALL
+                SELECT 'order', order_id, 'order_id',
+                       order_id, word_similarity($1, order_id)
+                FROM orders WHERE $1 <% order_id
+            ),
+            best AS (
+                SELECT DISTINCT ON (kind, id) kind, id, matched_field, matched_value, score
+                FROM candidates
+                ORDER BY kind, id, score DESC
+            )
+            SELECT kind, id, matched_field, matched_value, score
+            FROM best
+            ORDER BY score DESC, kind, id
+            LIMIT $2
+            "#,
+        )
+        .bind(term)
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Error running fuzzy search: {:?}", e);
+            e
+        })?;
+
+        tx.commit().await?;
+
+        Ok(hits)
+    }
+}
+
+#[async_trait]
+pub trait GeoRepository: Send + Sync {
+    async fn upsert_centroids(&self, centroids: &[ZipCentroid]) -> SqlxResult<u64>;
+    async fn find_centroid(&self, zip_code_prefix: &str) -> SqlxResult<Option<ZipCentroid>>;
+    async fn find_sellers_near(
+        &self,
+        origin: &ZipCentroid,
+        radius_km: f64,
+        limit: i64,
+    ) -> SqlxResult<Vec<NearbySeller>>;
+    async fn heatmap(
+        &self,
+        entity: HeatmapEntity,
+        level: HeatmapLevel,
+        purchased: &DateRange,
+        status: Option<&str>,
+    ) -> SqlxResult<Vec<HeatmapRow>>;
+}
+
+#[derive(Clone)]
+pub struct PgGeoRepository {
+    pool: PgPool,
+}
+
+impl PgGeoRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Mean Earth radius (IUGG), in kilometres.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+const CENTROID_UPSERT_CHUNK: usize = 5000;
+
+#[async_trait]
+impl GeoRepository for PgGeoRepository {
+    #[instrument(skip(self, centroids), fields(count = centroids.len()))]
+    async fn upsert_centroids(&self, centroids: &[ZipCentroid]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut affected = 0;
+
+        for chunk in centroids.chunks(CENTROID_UPSERT_CHUNK) {
+            let mut qb: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO zip_code_centroids \
+                 (zip_code_prefix, latitude, longitude, city, state, sample_count) ",
+            );
+            qb.push_values(chunk, |mut row, centroid| {
+                row.push_bind(&centroid.zip_code_prefix)
+                    .push_bind(centroid.latitude)
+                    .push_bind(centroid.longitude)
+                    .push_bind(&centroid.city)
+                    .push_bind(&centroid.state)
+                    .push_bind(centroid.sample_count);
+            });
+            qb.push(
+                " ON CONFLICT (zip_code_prefix) DO UPDATE SET \
+                 latitude = EXCLUDED.latitude, longitude = EXCLUDED.longitude, \
+                 city = EXCLUDED.city, state = EXCLUDED.state, \
+                 sample_count = EXCLUDED.sample_count",
+            );
+
+            affected += qb
+                .build()
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!("Error upserting zip code centroids: {:?}", e);
+                    e
+                })?
+                .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Upserted {} zip code centroids", affected);
+
+        Ok(affected)
+    }
+
+    async fn find_centroid(&self, zip_code_prefix: &str) -> SqlxResult<Option<ZipCentroid>> {
+        sqlx::query_as::<_, ZipCentroid>(
+            r#"
+            SELECT zip_code_prefix, latitude, longitude, city, state, sample_count
+            FROM zip_code_centroids
+            WHERE zip_code_prefix = $1
+            "#,
+        )
+        .bind(zip_code_prefix)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(
+                "Error fetching zip code centroid {}: {:?}",
+                zip_code_prefix, e
+            );
+            e
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn find_sellers_near(
+        &self,
+        origin: &ZipCentroid,
+        radius_km: f64,
+        limit: i64,
+    ) -> SqlxResult<Vec<NearbySeller>> {
+        let bounds = BoundingBox::around(origin.latitude, origin.longitude, radius_km);
+
+        // The bounding box narrows candidates through the (latitude, longitude)
+        // index; the haversine distance then trims its corners.
+        sqlx::query_as::<_, NearbySeller>(
+            r#"
+            SELECT
+                s.seller_id, s.seller_zip_code_prefix, s.seller_city, s.seller_state,
+                d.distance_km
+            FROM zip_code_centroids c
+            JOIN sellers s ON s.seller_zip_code_prefix = c.zip_code_prefix
+            CROSS JOIN LATERAL (
+                SELECT 2 * $3 * asin(sqrt(
+                    power(sin(radians(c.latitude - $1) / 2), 2)
+                    + cos(radians($1)) * cos(radians(c.latitude))
+                      * power(sin(radians(c.longitude - $2) / 2), 2)
+                )) AS distance_km
+            ) d
+            WHERE c.latitude BETWEEN $4 AND $5
+              AND c.longitude BETWEEN $6 AND $7
+              AND d.distance_km <= $8
+            ORDER BY d.distance_km, s.seller_id
+            LIMIT $9
+            "#,
+        )
+        .bind(origin.latitude)
+        .bind(origin.longitude)
+        .bind(EARTH_RADIUS_KM)
+        .bind(bounds.min_latitude)
+        .bind(bounds.max_latitude)
+        .bind(bounds.min_longitude)
+        .bind(bounds.max_longitude)
+        .bind(radius_km)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(
+                "Error fetching sellers near {}: {:?}",
+                origin.zip_code_prefix, e
+            );
+            e
+        })
+    }
+
+    /// Date range and status only narrow down orders.
+    #[instrument(skip(self))]
+    async fn heatmap(
+        &self,
+        entity: HeatmapEntity,
+        level: HeatmapLevel,
+        purchased: &DateRange,
+        status: Option<&str>,
+    ) -> SqlxResult<Vec<HeatmapRow>> {
+        let (zip, city, state, group) = match level {
+            HeatmapLevel::Zip => ("p.zip", "MIN(p.city)", "MIN(p.state)", "p.zip"),
+            HeatmapLevel::City => ("NULL::text", "p.city", "p.state", "p.city, p.state"),
+            HeatmapLevel::State => ("NULL::text", "NULL::text", "p.state", "p.state"),
+        };
+
+        let mut qb = QueryBuilder::new(format!(
+            r#"
+            SELECT
+                {zip} AS zip,
+                {city} AS city,
+                {state} AS state,
+                COUNT(*) AS count,
+                COUNT(*) FILTER (WHERE z.zip_code_prefix IS NULL) AS unlocated,
+                AVG(z.latitude) AS latitude,
+                AVG(z.longitude) AS longitude,
+                AVG(p.delivery_days) AS avg_delivery_days,
+                AVG(CASE WHEN p.days_late <= 0 THEN 1.0 ELSE 0.0 END)
+                    FILTER (WHERE p.days_late IS NOT NULL)::float8 AS on_time_rate
+            FROM ("#
+        ));
+        match entity {
+            HeatmapEntity::Customers => {
+                qb.push(
+                    "SELECT customer_zip_code_prefix AS zip, customer_city AS city, \
+                     customer_state AS state, NULL::float8 AS delivery_days, \
+                     NULL::float8 AS days_late \
+                     FROM customers",
+                );
+            }
+            HeatmapEntity::Sellers => {
+                qb.push(
+                    "SELECT seller_zip_code_prefix AS zip, seller_city AS city, \
+                     seller_state AS state, NULL::float8 AS delivery_days, \
+                     NULL::float8 AS days_late \
+                     FROM sellers",
+                );
+            }
+            HeatmapEntity::Orders => {
+                qb.push(
+                    "SELECT c.customer_zip_code_prefix AS zip, c.customer_city AS city, \
+                     c.customer_state AS state, \
+                     EXTRACT(EPOCH FROM o.order_delivered_customer_date \
+                         - o.order_purchase_timestamp)::float8 / 86400.0 AS delivery_days, \
+                     EXTRACT(EPOCH FROM o.order_delivered_customer_date \
+                         - o.order_estimated_delivery_date)::float8 / 86400.0 AS days_late \
+                     FROM orders o \
+                     JOIN customers c ON c.customer_id = o.customer_id \
+                     WHERE TRUE",
+                );
+                push_date_range(&mut qb, "o.order_purchase_timestamp", purchased);
+                if let Some(status) = status {
+                    qb.push(" AND o.order_status = ")
+                        .push_bind(status.to_string());
+                }
+            }
+        }
+        qb.push(format!(
+            ") p \
+             LEFT JOIN zip_code_centroids z ON z.zip_code_prefix = p.zip \
+             GROUP BY {group} \
+             ORDER BY 3, 2, 1"
+        ));
+
+        qb.build_query_as::<HeatmapRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error building {:?} heatmap: {:?}", entity, e);
+                e
+            })
+    }
+}
+
+#[async_trait]
+pub trait EstimateRepository: Send + Sync {
+    async fn transit_stats(
+        &self,
+        seller_state: Option<&str>,
+        customer_state: &str,
+        purchase_month: i32,
+    ) -> SqlxResult<Vec<DurationStats>>;
+    async fn handling_stats(&self, seller_id: Option<&str>) -> SqlxResult<Vec<DurationStats>>;
+}
+
+#[derive(Clone)]
+pub struct PgEstimateRepository {
+    pool: PgPool,
+}
+
+impl PgEstimateRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl EstimateRepository for PgEstimateRepository {
+    /// Every level matching the route and month; route levels only when
+    /// `seller_state` is given.
+    #[instrument(skip(self))]
+    async fn transit_stats(
+        &self,
+        seller_state: Option<&str>,
+        customer_state: &str,
+        purchase_month: i32,
+    ) -> SqlxResult<Vec<DurationStats>> {
+        sqlx::query_as::<_, DurationStats>(
+            r#"
+            SELECT level, samples, p10_days, p50_days, p90_days
+            FROM mv_transit_times
+            WHERE (level = 'route_month' AND seller_state = $1
+                   AND customer_state = $2 AND purchase_month = $3)
+               OR (level = 'route' AND seller_state = $1 AND customer_state = $2)
+               OR (level = 'destination_month' AND customer_state = $2
+                   AND purchase_month = $3)
+               OR (level = 'destination' AND customer_state = $2)
+               OR level = 'all'
+            "#,
+        )
+        .bind(seller_state)
+        .bind(customer_state)
+        .bind(purchase_month)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error fetching transit times: {:?}", e);
+            e
+        })
+    }
+
+    /// The seller's own row, if any, and the row covering all sellers.
+    #[instrument(skip(self))]
+    async fn handling_stats(&self, seller_id: Option<&str>) -> SqlxResult<Vec<DurationStats>> {
+        sqlx::query_as::<_, DurationStats>(
+            r#"
+            SELECT
+                CASE WHEN seller_id = '' THEN 'all' ELSE 'seller' END AS level,
+                samples, p10_days, p50_days, p90_days
+            FROM mv_seller_handling
+            WHERE seller_id = '' OR seller_id = $1
+            "#,
+        )
+        .bind(seller_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error fetching seller handling times: {:?}", e);
+            e
+        })
+    }
+}
+
+#[async_trait]
+pub trait AnalyticsRepository: Send + Sync {
+    async fn delivery_stats(
+        &self,
+        group_by: Option<DeliveryGroupBy>,
+        purchased: &DateRange,
+    ) -> SqlxResult<Vec<DeliveryStats>>;
+    async fn order_timeseries(
+        &self,
+        interval: TimeseriesInterval,
+        purchased: &DateRange,
+        customer_state: Option<&str>,
+        split_by_status: bool,
+    ) -> SqlxResult<Vec<TimeseriesRow>>;
+    async fn purchase_span(
+        &self,
+        customer_state: Option<&str>,
+    ) -> SqlxResult<Option<(chrono::NaiveDate, chrono::NaiveDate)>>;
+    async fn cohort_cells(
+        &self,
+        first_purchase: &DateRange,
+        customer_state: Option<&str>,
+        periods: i32,
+    ) -> SqlxResult<Vec<CohortCell>>;
+    async fn funnel_counts(&self, purchased: &DateRange) -> SqlxResult<FunnelCounts>;
+    async fn state_flows(&self, purchased: &DateRange) -> SqlxResult<Vec<StateFlow>>;
+    async fn review_stats(
+        &self,
+        group_by: Option<ReviewGroupBy>,
+        purchased: &DateRange,
+        min_reviews: i64,
+        limit: i64,
+    ) -> SqlxResult<Vec<ReviewStats>>;
+    async fn lateness_buckets(&self, purchased: &DateRange) -> SqlxResult<Vec<LatenessBucket>>;
+    async fn payment_totals(&self, purchased: &DateRange) -> SqlxResult<PaymentTotals>;
+    async fn payment_type_stats(&self, purchased: &DateRange) -> SqlxResult<Vec<PaymentTypeStats>>;
+    async fn installment_counts(&self, purchased: &DateRange) -> SqlxResult<Vec<InstallmentCount>>;
+    async fn ticket_by_state(&self, purchased: &DateRange) -> SqlxResult<Vec<StateTicket>>;
+    async fn refresh_view(&self, view: &str) -> SqlxResult<()>;
+    async fn record_refresh(&self, dataset: &str, duration_ms: i64) -> SqlxResult<()>;
+    async fn refresh_log(&self) -> SqlxResult<Vec<DatasetRefresh>>;
+    async fn rfm_histories(&self) -> SqlxResult<Vec<RfmHistory>>;
+    async fn replace_rfm(&self, people: &[CustomerRfm]) -> SqlxResult<u64>;
+    async fn find_rfm(&self, customer_unique_id: &str) -> SqlxResult<Option<CustomerRfm>>;
+    async fn rfm_segments(&self) -> SqlxResult<Vec<RfmSegmentSummary>>;
+}
+
+pub const ORDER_FACTS_VIEW: &str = "mv_order_facts";
+pub const ORDER_SHIPMENTS_VIEW: &str = "mv_order_shipments";
+pub const REVIEW_FACTS_VIEW: &str = "mv_review_facts";
+pub const PAYMENT_FACTS_VIEW: &str = "mv_payment_facts";
+pub const TRANSIT_TIMES_VIEW: &str = "mv_transit_times";
+pub const SELLER_HANDLING_VIEW: &str = "mv_seller_handling";
+
+/// Materialized views behind the analytics endpoints, in refresh order.
+pub const ANALYTICS_VIEWS: &[&str] = &[
+    ORDER_FACTS_VIEW,
+    ORDER_SHIPMENTS_VIEW,
+    REVIEW_FACTS_VIEW,
+    PAYMENT_FACTS_VIEW,
+    TRANSIT_TIMES_VIEW,
+    SELLER_HANDLING_VIEW,
+];
+
+/// Refresh log entry of the `customer_rfm` table.
+pub const RFM_DATASET: &str = "customer_rfm";
+
+#[derive(Clone)]
+pub struct PgAnalyticsRepository {
+    pool: PgPool,
+}
+
+impl PgAnalyticsRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AnalyticsRepository for PgAnalyticsRepository {
+    #[instrument(skip(self))]
+    async fn delivery_stats(
+        &self,
+        group_by: Option<DeliveryGroupBy>,
+        purchased: &DateRange,
+    ) -> SqlxResult<Vec<DeliveryStats>> {
+        let key = match group_by {
+            Some(DeliveryGroupBy::State) => "customer_state",
+            Some(DeliveryGroupBy::Month) => "to_char(order_purchase_timestamp, 'YYYY-MM')",
+            Some(DeliveryGroupBy::Status) => "order_status",
+            None => "'all'",
+        };
+
+        let mut qb = QueryBuilder::new(format!(
+            r#"
+            SELECT
+                {key}::text AS key,
+                COUNT(*) AS order_count,
+                COUNT(order_delivered_customer_date) AS delivered_count,
+                AVG(CASE WHEN days_late <= 0 THEN 1.0 ELSE 0.0 END)
+                    FILTER (WHERE delivery_days IS NOT NULL)::float8 AS on_time_rate,
+                AVG(delivery_days) AS avg_delivery_days,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY delivery_days) AS median_delivery_days,
+                percentile_cont(0.9) WITHIN GROUP (ORDER BY delivery_days) AS p90_delivery_days,
+                AVG(days_late) FILTER (WHERE days_late > 0) AS avg_days_late,
+                AVG(handoff_days) AS avg_carrier_handoff_days
+            FROM mv_order_facts
+            WHERE TRUE"#
+        ));
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY 1 ORDER BY 1");
+
+        qb.build_query_as::<DeliveryStats>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing delivery analytics: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn order_timeseries(
+        &self,
+        interval: TimeseriesInterval,
+        purchased: &DateRange,
+        customer_state: Option<&str>,
+        split_by_status: bool,
+    ) -> SqlxResult<Vec<TimeseriesRow>> {
+        let unit = interval.as_sql();
+
+        let mut qb = QueryBuilder::new(
+            "WITH filtered AS (\
+             SELECT order_purchase_timestamp, order_status FROM mv_order_facts WHERE TRUE",
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        if let Some(state) = customer_state {
+            qb.push(" AND customer_state = ")
+                .push_bind(state.to_string());
+        }
+
+        // Buckets span the requested range, or the data when it is open-ended,
+        // so that empty periods are reported with a zero count.
+        qb.push("), bounds AS (SELECT date_trunc(")
+            .push_bind(unit)
+            .push(", COALESCE(")
+            .push_bind(purchased.start())
+            .push(", MIN(order_purchase_timestamp))) AS lo, date_trunc(")
+            .push_bind(unit)
+            .push(", COALESCE(")
+            .push_bind(purchased.to.and_then(|d| d.and_hms_opt(0, 0, 0)))
+            .push(", MAX(order_purchase_timestamp))) AS hi FROM filtered)")
+            .push(", buckets AS (SELECT generate_series(lo, hi, ('1 ' || ")
+            .push_bind(unit)
+            .push(")::interval) AS bucket FROM bounds)");
+
+        if split_by_status {
+            qb.push(", series AS (SELECT DISTINCT order_status AS status FROM filtered)");
+        } else {
+            qb.push(", series AS (SELECT NULL::text AS status)");
+        }
+
+        qb.push(
+            " SELECT s.status, b.bucket::date AS bucket, COUNT(f.order_purchase_timestamp) AS order_count \
+             FROM buckets b CROSS JOIN series s \
+             LEFT JOIN filtered f ON date_trunc(",
+        )
+        .push_bind(unit)
+        .push(", f.order_purchase_timestamp) = b.bucket");
+        if split_by_status {
+            qb.push(" AND f.order_status = s.status");
+        }
+        qb.push(" GROUP BY s.status, b.bucket ORDER BY s.status, b.bucket");
+
+        qb.build_query_as::<TimeseriesRow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing order timeseries: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn purchase_span(
+        &self,
+        customer_state: Option<&str>,
+    ) -> SqlxResult<Option<(chrono::NaiveDate, chrono::NaiveDate)>> {
+        let mut qb = QueryBuilder::new(
+            "SELECT MIN(order_purchase_timestamp)::date, MAX(order_purchase_timestamp)::date \
+             FROM mv_order_facts WHERE TRUE",
+        );
+        if let Some(state) = customer_state {
+            qb.push(" AND customer_state = ")
+                .push_bind(state.to_string());
+        }
+
+        let (first, last): (Option<chrono::NaiveDate>, Option<chrono::NaiveDate>) = qb
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error finding purchase span: {:?}", e);
+                e
+            })?;
+        Ok(first.zip(last))
+    }
+
+    #[instrument(skip(self))]
+    async fn cohort_cells(
+        &self,
+        first_purchase: &DateRange,
+        customer_state: Option<&str>,
+        periods: i32,
+    ) -> SqlxResult<Vec<CohortCell>> {
+        // People are identified by customer_unique_id: the dataset issues a
+        // new customer_id for every order.
+        let mut qb = QueryBuilder::new(
+            "WITH purchases AS (\
+             SELECT customer_unique_id AS person, \
+             date_trunc('month', order_purchase_timestamp) AS month \
+             FROM mv_order_facts WHERE TRUE",
+        );
+        if let Some(state) = customer_state {
+            qb.push(" AND customer_state = ")
+                .push_bind(state.to_string());
+        }
+        qb.push(
+            " GROUP BY 1, 2), \
+             cohorts AS (SELECT person, MIN(month) AS cohort FROM purchases GROUP BY person), \
+             cells AS (\
+             SELECT f.cohort, \
+             ((EXTRACT(YEAR FROM p.month) - EXTRACT(YEAR FROM f.cohort)) * 12 \
+             + EXTRACT(MONTH FROM p.month) - EXTRACT(MONTH FROM f.cohort))::int AS period, \
+             p.person \
+             FROM cohorts f JOIN purchases p ON p.person = f.person WHERE TRUE",
+        );
+        push_date_range(&mut qb, "f.cohort", first_purchase);
+        qb.push(
+            "), last_month AS (SELECT MAX(month) AS month FROM purchases) \
+             SELECT cells.cohort::date AS cohort, cells.period, COUNT(*) AS people, \
+             ((EXTRACT(YEAR FROM l.month) - EXTRACT(YEAR FROM cells.cohort)) * 12 \
+             + EXTRACT(MONTH FROM l.month) - EXTRACT(MONTH FROM cells.cohort))::int AS observed_periods \
+             FROM cells CROSS JOIN last_month l WHERE cells.period <= ",
+        )
+        .push_bind(periods)
+        .push(" GROUP BY cells.cohort, cells.period, l.month ORDER BY cells.cohort, cells.period");
+
+        qb.build_query_as::<CohortCell>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing cohort retention: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn funnel_counts(&self, purchased: &DateRange) -> SqlxResult<FunnelCounts> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                COUNT(*) AS purchased,
+                COUNT(*) FILTER (WHERE approved) AS approved,
+                COUNT(*) FILTER (WHERE approved AND handed) AS handed_to_carrier,
+                COUNT(*) FILTER (WHERE approved AND handed AND delivered) AS delivered,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY approval_days)
+                    AS median_days_to_approval,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY handoff_days)
+                    AS median_days_to_carrier,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY carrier_to_customer_days)
+                    AS median_days_to_delivery
+            FROM mv_order_facts
+            CROSS JOIN LATERAL (
+                SELECT
+                    order_approved_at IS NOT NULL AS approved,
+                    order_delivered_carrier_date IS NOT NULL AS handed,
+                    order_delivered_customer_date IS NOT NULL AS delivered
+            ) s
+            WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+
+        qb.build_query_as::<FunnelCounts>()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing order funnel: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn state_flows(&self, purchased: &DateRange) -> SqlxResult<Vec<StateFlow>> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                seller_state,
+                customer_state,
+                COUNT(*) AS orders,
+                AVG(delivery_days) AS avg_delivery_days
+            FROM mv_order_shipments
+            WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY seller_state, customer_state ORDER BY seller_state, customer_state");
+
+        qb.build_query_as::<StateFlow>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing state flows: {:?}", e);
+                e
+            })
+    }
+
+    /// A review counts once per seller or category of the order it rates.
+    #[instrument(skip(self))]
+    async fn review_stats(
+        &self,
+        group_by: Option<ReviewGroupBy>,
+        purchased: &DateRange,
+        min_reviews: i64,
+        limit: i64,
+    ) -> SqlxResult<Vec<ReviewStats>> {
+        let (key, join) = match group_by {
+            Some(ReviewGroupBy::Seller) => (
+                "i.seller_id",
+                "JOIN (SELECT DISTINCT order_id, seller_id FROM order_items) i \
+                 ON i.order_id = f.order_id",
+            ),
+            Some(ReviewGroupBy::Category) => (
+                "c.category",
+                "JOIN (\
+                     SELECT DISTINCT i.order_id, \
+                         COALESCE(p.product_category_name, 'unknown') AS category \
+                     FROM order_items i \
+                     LEFT JOIN products p ON p.product_id = i.product_id\
+                 ) c ON c.order_id = f.order_id",
+            ),
+            Some(ReviewGroupBy::State) => ("f.customer_state", ""),
+            None => ("'all'", ""),
+        };
+
+        let mut qb = QueryBuilder::new(format!(
+            r#"
+            SELECT
+                {key}::text AS key,
+                COUNT(*) AS review_count,
+                AVG(f.review_score)::float8 AS avg_score,
+                AVG(f.review_score) FILTER (WHERE f.days_late <= 0)::float8 AS avg_score_on_time,
+                AVG(f.review_score) FILTER (WHERE f.days_late > 0)::float8 AS avg_score_late,
+                AVG(CASE WHEN f.days_late > 0 THEN 1.0 ELSE 0.0 END)
+                    FILTER (WHERE f.days_late IS NOT NULL)::float8 AS late_rate,
+                corr(f.review_score, f.days_late) AS score_lateness_correlation
+            FROM mv_review_facts f
+            {join}
+            WHERE TRUE"#
+        ));
+        push_date_range(&mut qb, "f.order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY 1 HAVING COUNT(*) >= ")
+            .push_bind(min_reviews)
+            .push(" ORDER BY review_count DESC, key LIMIT ")
+            .push_bind(limit);
+
+        qb.build_query_as::<ReviewStats>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing review analytics: {:?}", e);
+                e
+            })
+    }
+
+    /// Buckets are whole days late, rounded up; empty buckets are left out.
+    #[instrument(skip(self))]
+    async fn lateness_buckets(&self, purchased: &DateRange) -> SqlxResult<Vec<LatenessBucket>> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                days_late,
+                COUNT(*) AS review_count,
+                AVG(review_score)::float8 AS avg_score
+            FROM (
+                SELECT
+                    review_score,
+                    CASE
+                        WHEN days_late IS NULL THEN 'undelivered'
+                        WHEN days_late <= 0 THEN 'on_time'
+                        WHEN days_late <= 3 THEN '1-3'
+                        WHEN days_late <= 7 THEN '4-7'
+                        WHEN days_late <= 14 THEN '8-14'
+                        ELSE '15+'
+                    END AS days_late,
+                    CASE
+                        WHEN days_late IS NULL THEN 5
+                        WHEN days_late <= 0 THEN 0
+                        WHEN days_late <= 3 THEN 1
+                        WHEN days_late <= 7 THEN 2
+                        WHEN days_late <= 14 THEN 3
+                        ELSE 4
+                    END AS position
+                FROM mv_review_facts
+                WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(") b GROUP BY days_late, position ORDER BY position");
+
+        qb.build_query_as::<LatenessBucket>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing review lateness buckets: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn payment_totals(&self, purchased: &DateRange) -> SqlxResult<PaymentTotals> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                COUNT(*) AS order_count,
+                COALESCE(SUM(order_value), 0)::float8 AS total_value,
+                COUNT(*) FILTER (WHERE payment_types > 1) AS multi_method_orders
+            FROM (
+                SELECT
+                    order_id,
+                    SUM(payment_value) AS order_value,
+                    COUNT(DISTINCT payment_type) AS payment_types
+                FROM mv_payment_facts
+                WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY order_id) o");
+
+        qb.build_query_as::<PaymentTotals>()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing payment totals: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn payment_type_stats(&self, purchased: &DateRange) -> SqlxResult<Vec<PaymentTypeStats>> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                payment_type,
+                COUNT(*) AS payments,
+                COUNT(DISTINCT order_id) AS orders,
+                SUM(payment_value)::float8 AS total_value,
+                AVG(payment_value)::float8 AS avg_value,
+                AVG(payment_installments)::float8 AS avg_installments
+            FROM mv_payment_facts
+            WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY payment_type ORDER BY total_value DESC");
+
+        qb.build_query_as::<PaymentTypeStats>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing payment type stats: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn installment_counts(&self, purchased: &DateRange) -> SqlxResult<Vec<InstallmentCount>> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                payment_installments AS installments,
+                COUNT(*) AS payments,
+                AVG(payment_value)::float8 AS avg_value
+            FROM mv_payment_facts
+            WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(" GROUP BY payment_installments ORDER BY payment_installments");
+
+        qb.build_query_as::<InstallmentCount>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing installment counts: {:?}", e);
+                e
+            })
+    }
+
+    #[instrument(skip(self))]
+    async fn ticket_by_state(&self, purchased: &DateRange) -> SqlxResult<Vec<StateTicket>> {
+        let mut qb = QueryBuilder::new(
+            r#"
+            SELECT
+                customer_state AS state,
+                COUNT(*) AS orders,
+                SUM(order_value)::float8 AS total_value,
+                AVG(order_value)::float8 AS avg_ticket
+            FROM (
+                SELECT order_id, customer_state, SUM(payment_value) AS order_value
+                FROM mv_payment_facts
+                WHERE TRUE"#,
+        );
+        push_date_range(&mut qb, "order_purchase_timestamp", purchased);
+        qb.push(
+            " GROUP BY order_id, customer_state) o GROUP BY customer_state ORDER BY customer_state",
+        );
+
+        qb.build_query_as::<StateTicket>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error computing average ticket by state: {:?}", e);
+                e
+            })
+    }
+
+    /// Refreshes without blocking readers; relies on the view's unique index.
+    #[instrument(skip(self))]
+    async fn refresh_view(&self, view: &str) -> SqlxResult<()> {
+        let started = std::time::Instant::now();
+
+        sqlx::query(&format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {}", view))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Error refreshing {}: {:?}", view, e);
+                e
+            })?;
+
+        self.record_refresh(view, started.elapsed().as_millis() as i64)
+            .await
+    }
+
+    async fn record_refresh(&self, dataset: &str, duration_ms: i64) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO analytics_refresh_log (dataset, last_refreshed_at, duration_ms)
+            VALUES ($1, now() AT TIME ZONE 'UTC', $2)
+            ON CONFLICT (dataset) DO UPDATE SET
+                last_refreshed_at = EXCLUDED.last_refreshed_at,
+                duration_ms = EXCLUDED.duration_ms
+            "#,
+        )
+        .bind(dataset)
+        .bind(duration_ms)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error recording refresh of {}: {:?}", dataset, e);
+            e
+        })?;
+
+        Ok(())
+    }
+
+    async fn refresh_log(&self) -> SqlxResult<Vec<DatasetRefresh>> {
+        sqlx::query_as::<_, DatasetRefresh>(
+            r#"
+            SELECT dataset, last_refreshed_at, duration_ms
+            FROM analytics_refresh_log
+            ORDER BY dataset
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error fetching analytics refresh log: {:?}", e);
+            e
+        })
+    }
+
+    /// Purchase history of everyone with a completed order. Recency is
+    /// measured from the latest purchase in the dataset rather than today, as
+    /// the data is historical.
+    async fn rfm_histories(&self) -> SqlxResult<Vec<RfmHistory>> {
+        sqlx::query_as::<_, RfmHistory>(
+            r#"
+            WITH paid AS (
+                SELECT order_id, SUM(payment_value) AS value
+                FROM order_payments
+                GROUP BY order_id
+            ),
+            per_person AS (
+                SELECT
+                    c.customer_unique_id,
+                    MAX(o.order_purchase_timestamp) AS last_purchase_at,
+                    COUNT(*)::int AS frequency,
+                    COALESCE(SUM(paid.value), 0)::float8 AS monetary
+                FROM orders o
+                JOIN customers c ON c.customer_id = o.customer_id
+                LEFT JOIN paid ON paid.order_id = o.order_id
+                WHERE o.order_status NOT IN ('canceled', 'unavailable')
+                GROUP BY c.customer_unique_id
+            )
+            SELECT
+                p.customer_unique_id,
+                p.last_purchase_at,
+                EXTRACT(DAY FROM r.latest - p.last_purchase_at)::int AS recency_days,
+                p.frequency,
+                p.monetary
+            FROM per_person p
+            CROSS JOIN (SELECT MAX(last_purchase_at) AS latest FROM per_person) r
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error reading RFM purchase histories: {:?}", e);
+            e
+        })
+    }
+
+    /// Replaces `customer_rfm` in one transaction, so readers keep seeing the
+    /// previous run until it commits.
+    #[instrument(skip(self, people))]
+    async fn replace_rfm(&self, people: &[CustomerRfm]) -> SqlxResult<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM customer_rfm")
+            .execute(&mut *tx)
+            .await?;
+
+        let mut affected = 0;
+        for chunk in people.chunks(RFM_INSERT_CHUNK) {
+            let ids: Vec<&str> = chunk
+                .iter()
+                .map(|p| p.customer_unique_id.as_str())
+                .collect();
+            let last_purchases: Vec<_> = chunk.iter().map(|p| p.last_purchase_at).collect();
+            let recencies: Vec<i32> = chunk.iter().map(|p| p.recency_days).collect();
+            let frequencies: Vec<i32> = chunk.iter().map(|p| p.frequency).collect();
+            let monetaries: Vec<f64> = chunk.iter().map(|p| p.monetary).collect();
+            let r_scores: Vec<i16> = chunk.iter().map(|p| p.r_score).collect();
+            let f_scores: Vec<i16> = chunk.iter().map(|p| p.f_score).collect();
+            let m_scores: Vec<i16> = chunk.iter().map(|p| p.m_score).collect();
+            let segments: Vec<&str> = chunk.iter().map(|p| p.segment.as_str()).collect();
+            let computed_at: Vec<_> = chunk.iter().map(|p| p.computed_at).collect();
+
+            affected += sqlx::query(
+                r#"
+                INSERT INTO customer_rfm (
+                    customer_unique_id, last_purchase_at, recency_days, frequency, monetary,
+                    r_score, f_score, m_score, segment, computed_at
+                )
+                SELECT *
+                FROM UNNEST(
+                    $1::text[], $2::timestamp[], $3::int[], $4::int[], $5::float8[],
+                    $6::int2[], $7::int2[], $8::int2[], $9::text[], $10::timestamp[]
+                )
+                "#,
+            )
+            .bind(ids)
+            .bind(last_purchases)
+            .bind(recencies)
+            .bind(frequencies)
+            .bind(monetaries)
+            .bind(r_scores)
+            .bind(f_scores)
+            .bind(m_scores)
+            .bind(segments)
+            .bind(computed_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Error storing RFM scores: {:?}", e);
+                e
+            })?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        info!("Scored {} people for RFM", affected);
+
+        Ok(affected)
+    }
+
+    async fn find_rfm(&self, customer_unique_id: &str) -> SqlxResult<Option<CustomerRfm>> {
+        sqlx::query_as::<_, CustomerRfm>(
+            r#"
+            SELECT
+                customer_unique_id, last_purchase_at, recency_days, frequency, monetary,
+                r_score, f_score, m_score, segment, computed_at
+            FROM customer_rfm
+            WHERE customer_unique_id = $1
+            "#,
+        )
+        .bind(customer_unique_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error fetching RFM profile {}: {:?}", customer_unique_id, e);
+            e
+        })
+    }
+
+    async fn rfm_segments(&self) -> SqlxResult<Vec<RfmSegmentSummary>> {
+        sqlx::query_as::<_, RfmSegmentSummary>(
+            r#"
+            SELECT
+                segment,
+                COUNT(*) AS people,
+                AVG(recency_days)::float8 AS avg_recency_days,
+                AVG(frequency)::float8 AS avg_frequency,
+                AVG(monetary) AS avg_monetary
+            FROM customer_rfm
+            GROUP BY segment
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Error summarizing RFM segments: {:?}", e);
+            e
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use sqlx::Execute;
+
+    use crate::models::FieldKind;
+
+    fn customer_where(filter: LocationFilter) -> String {
+        let mut qb = QueryBuilder::new("SELECT customer_id FROM customers");
+        push_location_filter(&mut qb, &filter, "customer");
+        qb.sql().to_string()
+    }
+
+    #[test]
+    fn city_filter_matches_like_the_city_parameter() {
+        let by_parameter = customer_where(LocationFilter {
+            city: Some("Sao Paulo".to_string()),
+            ..Default::default()
+        });
+        let by_filter = customer_where(LocationFilter {
+            expr: FilterExpr::parse::<Customer>(Some("customer_city==são paulo")).unwrap(),
+            ..Default::default()
+        });
+
+        // Both compare the normalized column with the input normalized the
+        // same way, so accents and case in either value select the same rows.
+        let expected = "SELECT customer_id FROM customers WHERE TRUE \
+                        AND customer_city_normalized = lower(immutable_unaccent($1))";
+        assert_eq!(by_parameter, expected);
+        assert_eq!(by_filter, expected);
+    }
+
+    /// Values bound to `qb`, in placeholder order, decoded from the encoded
+    /// arguments.
+    fn binds(qb: &mut QueryBuilder<'_, Postgres>) -> Vec<String> {
+        let args = qb.build().take_arguments().unwrap().unwrap();
+        let debug = format!("{:?}", args);
+        let types: Vec<&str> = debug
+            .split("PgTypeInfo(")
+            .skip(1)
+            .map(|t| t.split(')').next().unwrap())
+            .collect();
+        let bytes: Vec<u8> = debug
+            .split("buffer: [")
+            .nth(1)
+            .and_then(|rest| rest.split(']').next())
+            .unwrap()
+            .split(", ")
+            .map(|b| b.parse().unwrap())
+            .collect();
+
+        let mut values = Vec::new();
+        let mut rest = &bytes[..];
+        for kind in types {
+            let (len, tail) = rest.split_at(4);
+            let (value, tail) = tail.split_at(u32::from_be_bytes(len.try_into().unwrap()) as usize);
+            values.push(match kind {
+                "Text" => String::from_utf8(value.to_vec()).unwrap(),
+                "Timestamp" => {
+                    let micros = i64::from_be_bytes(value.try_into().unwrap());
+                    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)
+                        .unwrap()
+                        .and_hms_opt(0, 0, 0);
+                    (epoch.unwrap() + chrono::Duration::microseconds(micros)).to_string()
+                }
+                other => panic!("unexpected bind type {}", other),
+            });
+            rest = tail;
+        }
+        values
+    }
+
+    fn order_sort() -> SortSpec {
+        SortSpec::parse::<Order>(Some("-order_purchase_timestamp,order_status")).unwrap()
+    }
+
+    fn order_after() -> Vec<SqlValue> {
+        vec![
+            SqlValue::parse(FieldKind::Timestamp, "2017-10-02T10:56:33").unwrap(),
+            SqlValue::Text("delivered".to_string()),
+            SqlValue::Text("o1".to_string()),
+        ]
+    }
+
+    fn keyset_where(
+        direction: CursorDirection,
+        inclusive: bool,
+    ) -> QueryBuilder<'static, Postgres> {
+        let mut qb = QueryBuilder::new("SELECT order_id FROM orders WHERE TRUE");
+        push_keyset_predicate(&mut qb, &order_sort(), &order_after(), direction, inclusive);
+        push_order_by(&mut qb, &order_sort(), direction);
+        qb
+    }
+
+    #[test]
+    fn keyset_predicate_expands_mixed_directions() {
+        let mut qb = keyset_where(CursorDirection::Next, false);
+        assert_eq!(
+            qb.sql(),
+            "SELECT order_id FROM orders WHERE TRUE AND (\
+             (order_purchase_timestamp < $1) OR \
+             (order_purchase_timestamp = $2 AND order_status > $3) OR \
+             (order_purchase_timestamp = $4 AND order_status = $5 AND order_id > $6)) \
+             ORDER BY order_purchase_timestamp DESC, order_status ASC, order_id ASC"
+        );
+        assert_eq!(
+            binds(&mut qb),
+            [
+                "2017-10-02 10:56:33",
+                "2017-10-02 10:56:33",
+                "delivered",
+                "2017-10-02 10:56:33",
+                "delivered",
+                "o1",
+            ]
+        );
+    }
+
+    #[test]
+    fn keyset_predicate_walking_backwards_flips_every_comparison() {
+        let mut qb = keyset_where(CursorDirection::Prev, false);
+        assert_eq!(
+            qb.sql(),
+            "SELECT order_id FROM orders WHERE TRUE AND (\
+             (order_purchase_timestamp > $1) OR \
+             (order_purchase_timestamp = $2 AND order_status < $3) OR \
+             (order_purchase_timestamp = $4 AND order_status = $5 AND order_id < $6)) \
+             ORDER BY order_purchase_timestamp ASC, order_status DESC, order_id DESC"
+        );
+        assert_eq!(binds(&mut qb).len(), 6);
+    }
+
+    #[test]
+    fn inclusive_keyset_predicate_selects_the_cursor_row() {
+        let qb = keyset_where(CursorDirection::Prev, true);
+        assert!(
+            qb.sql().contains(
+                "(order_purchase_timestamp = $4 AND order_status = $5 AND order_id <= $6))"
+            )
+        );
+        assert!(qb.sql().contains("(order_purchase_timestamp > $1) OR "));
     }
 }
@@ -0,0 +1,333 @@
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, post, put},
+};
+use chrono::{DateTime, Utc};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::error::{AppError, problem_instance};
+use crate::handlers::{
+    cohorts_handler, create_customer_handler, create_order_handler, create_seller_handler,
+    delete_customer_handler, delivery_analytics_handler, delivery_estimate_handler, flows_handler,
+    funnel_handler, get_customer_by_id_handler, get_customer_nearby_sellers_handler,
+    get_customer_orders_handler, get_customers_handler, get_nearby_sellers_handler,
+    get_order_by_id_handler, get_orders_handler, get_seller_by_id_handler,
+    get_seller_rating_handler, get_sellers_handler, heatmap_handler, load_data_from_csv_handler,
+    lookup_customers_handler, lookup_orders_handler, lookup_sellers_handler,
+    order_timeseries_handler, payments_analytics_handler, person_rfm_handler,
+    recompute_rfm_handler, refresh_analytics_handler, reviews_analytics_handler,
+    rfm_segments_handler, search_handler, update_customer_handler,
+};
+use crate::openapi::ApiDoc;
+use crate::state::AppState;
+
+/// Prefix of the current API version.
+pub const CURRENT_VERSION: &str = "/v1";
+
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Deprecation of a group of routes, advertised with the `Deprecation`
+/// (RFC 9745) and `Sunset` (RFC 8594) response headers.
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    pub since: DateTime<Utc>,
+    /// When the routes are expected to stop responding.
+    pub sunset: Option<DateTime<Utc>>,
+    /// Version prefix serving the replacement, linked as `successor-version`
+    /// under the same path.
+    pub successor: Option<&'static str>,
+}
+
+impl Deprecation {
+    /// Marks every route of `router` as deprecated.
+    pub fn apply<S>(self, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        router.layer(middleware::from_fn_with_state(self, deprecation_headers))
+    }
+}
+
+async fn deprecation_headers(
+    State(deprecation): State<Deprecation>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let successor = deprecation.successor.map(|prefix| {
+        format!(
+            "<{}{}>; rel=\"successor-version\"",
+            prefix,
+            request.uri().path()
+        )
+    });
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    let since = format!("@{}", deprecation.since.timestamp());
+    if let Ok(value) = HeaderValue::from_str(&since) {
+        headers.insert(DEPRECATION, value);
+    }
+    if let Some(sunset) = deprecation.sunset {
+        let value = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(SUNSET, value);
+        }
+    }
+    if let Some(link) = successor.and_then(|l| HeaderValue::from_str(&l).ok()) {
+        headers.append(header::LINK, link);
+    }
+
+    response
+}
+
+fn v1_routes() -> Router<AppState> {
+    Router::new()
+        .route("/load-data", post(load_data_from_csv_handler))
+        .route("/customers", post(create_customer_handler))
+        .route("/customers", get(get_customers_handler))
+        .route("/customers:lookup", post(lookup_customers_handler))
+        .route("/customers/{id}", get(get_customer_by_id_handler))
+        .route("/customers/{id}", put(update_customer_handler))
+        .route("/customers/{id}", delete(delete_customer_handler))
+        .route("/customers/{id}/orders", get(get_customer_orders_handler))
+        .route(
+            "/customers/{id}/nearby-sellers",
+            get(get_customer_nearby_sellers_handler),
+        )
+        .route("/sellers", post(create_seller_handler))
+        .route("/sellers", get(get_sellers_handler))
+        .route("/sellers:lookup", post(lookup_sellers_handler))
+        .route("/sellers/nearby", get(get_nearby_sellers_handler))
+        .route("/sellers/{id}", get(get_seller_by_id_handler))
+        .route("/sellers/{id}/rating", get(get_seller_rating_handler))
+        .route("/orders", post(create_order_handler))
+        .route("/orders", get(get_orders_handler))
+        .route("/orders:lookup", post(lookup_orders_handler))
+        .route("/orders/{id}", get(get_order_by_id_handler))
+        .route("/search", get(search_handler))
+        .route("/geo/heatmap", get(heatmap_handler))
+        .route("/estimates/delivery", get(delivery_estimate_handler))
+        .route("/analytics/delivery", get(delivery_analytics_handler))
+        .route(
+            "/analytics/orders/timeseries",
+            get(order_timeseries_handler),
+        )
+        .route("/analytics/cohorts", get(cohorts_handler))
+        .route("/analytics/funnel", get(funnel_handler))
+        .route("/analytics/flows", get(flows_handler))
+        .route("/analytics/reviews", get(reviews_analytics_handler))
+        .route("/analytics/payments", get(payments_analytics_handler))
+        .route("/analytics/rfm/segments", get(rfm_segments_handler))
+        .route("/people/{id}/rfm", get(person_rfm_handler))
+        .route("/admin/rfm/recompute", post(recompute_rfm_handler))
+        .route("/admin/analytics/refresh", post(refresh_analytics_handler))
+        .method_not_allowed_fallback(method_not_allowed)
+}
+
+/// Routes whose contract changed in v2. Paths not listed here fall through
+/// to their v1 handlers, so a breaking change only needs its new handler
+/// registered below; a path registered here must list all of its methods.
+fn v2_routes() -> Router<AppState> {
+    Router::new().method_not_allowed_fallback(method_not_allowed)
+}
+
+async fn not_found() -> AppError {
+    AppError::NotFound
+}
+
+async fn method_not_allowed() -> AppError {
+    AppError::MethodNotAllowed
+}
+
+/// Builds the application router: the API under `/v1` and `/v2`, the
+/// unversioned paths as deprecated aliases of `/v1`, and the API docs.
+pub fn app(
+    state: AppState,
+    unversioned_deprecated_since: DateTime<Utc>,
+    unversioned_sunset: Option<DateTime<Utc>>,
+) -> Router {
+    let v1 = v1_routes().with_state(state.clone());
+    let v2 = v2_routes()
+        .with_state(state)
+        .fallback_service(v1.clone().fallback(not_found));
+
+    let unversioned = Deprecation {
+        since: unversioned_deprecated_since,
+        sunset: unversioned_sunset,
+        successor: Some(CURRENT_VERSION),
+    }
+    .apply(v1.clone());
+
+    Router::new()
+        .nest(CURRENT_VERSION, v1)
+        .nest("/v2", v2)
+        .merge(unversioned)
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+        .fallback(not_found)
+        .layer(middleware::from_fn(problem_instance))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Runs `request` through the router, deprecating the unversioned paths
+    /// on 2026-10-18 with a sunset on 2027-06-30. The pool never connects, so
+    /// only requests rejected before reaching the database can be tested.
+    async fn call(request: Request) -> Response {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .expect("lazy pool");
+        let day = |y, m, d| {
+            chrono::NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_time(chrono::NaiveTime::MIN)
+                .and_utc()
+        };
+        app(
+            AppState::new(pool),
+            day(2026, 10, 18),
+            Some(day(2027, 6, 30)),
+        )
+        .oneshot(request)
+        .await
+        .expect("router is infallible")
+    }
+
+    async fn send(request: Request) -> (StatusCode, Option<String>, Value) {
+        let response = call(request).await;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn post_json(uri: &str, body: Value) -> Request {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn pointers(problem: &Value) -> Vec<&str> {
+        problem["errors"]
+            .as_array()
+            .map(|errors| {
+                errors
+                    .iter()
+                    .map(|e| e["pointer"].as_str().unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn bad_json_body_points_at_the_field() {
+        let (status, content_type, problem) =
+            send(post_json("/v1/customers", json!({ "customer_id": 42 }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        assert_eq!(problem["code"], "bad_request");
+        assert_eq!(problem["instance"], "/v1/customers");
+        assert_eq!(pointers(&problem), ["/customer_id"]);
+
+        let (_, _, problem) = send(post_json(
+            "/v1/customers:lookup",
+            json!({ "ids": ["a", 1] }),
+        ))
+        .await;
+        assert_eq!(problem["errors"][0]["field"], "ids[1]");
+        assert_eq!(pointers(&problem), ["/ids/1"]);
+    }
+
+    #[tokio::test]
+    async fn failed_validation_points_at_every_field() {
+        let body = json!({
+            "customer_id": "c1",
+            "customer_unique_id": "u1",
+            "customer_zip_code_prefix": "123",
+            "customer_city": "sao paulo",
+            "customer_state": "SAO"
+        });
+        let (status, content_type, problem) = send(post_json("/v1/customers", body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        assert_eq!(problem["code"], "validation_failed");
+        assert_eq!(
+            pointers(&problem),
+            ["/customer_state", "/customer_zip_code_prefix"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_query_param_points_at_the_param() {
+        let request = Request::get("/v1/customers?limit=many")
+            .body(Body::empty())
+            .unwrap();
+        let (status, content_type, problem) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        assert_eq!(problem["code"], "bad_request");
+        assert_eq!(problem["errors"][0]["field"], "limit");
+        assert_eq!(pointers(&problem), ["/limit"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_a_problem_without_field_errors() {
+        let request = Request::get("/v1/nope").body(Body::empty()).unwrap();
+        let (status, content_type, problem) = send(request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        assert_eq!(problem["code"], "not_found");
+        assert_eq!(problem["instance"], "/v1/nope");
+        assert!(problem.get("errors").is_none());
+    }
+
+    #[tokio::test]
+    async fn unversioned_paths_announce_the_configured_dates() {
+        let header = |response: &Response, name: &HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+
+        let request = Request::get("/customers?limit=many")
+            .body(Body::empty())
+            .unwrap();
+        let response = call(request).await;
+        assert_eq!(
+            header(&response, &DEPRECATION).as_deref(),
+            Some("@1792281600")
+        );
+        assert_eq!(
+            header(&response, &SUNSET).as_deref(),
+            Some("Wed, 30 Jun 2027 00:00:00 GMT")
+        );
+        assert_eq!(
+            header(&response, &header::LINK).as_deref(),
+            Some("</v1/customers>; rel=\"successor-version\"")
+        );
+
+        let request = Request::get("/v1/customers?limit=many")
+            .body(Body::empty())
+            .unwrap();
+        assert!(header(&call(request).await, &DEPRECATION).is_none());
+    }
+}
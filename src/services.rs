@@ -1,22 +1,93 @@
+use chrono::{Datelike, SubsecRound};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tracing::instrument;
+use std::time::{Duration, Instant};
+use tracing::{error, info, instrument};
 use validator::Validate;
 
 use crate::error::{AppError, AppResult};
 use crate::models::{
-    CreateCustomerDto, CreateOrderDto, CreateSellerDto, Customer, LocationSearchQuery, Order,
-    OrderSearchQuery, PaginatedResponse, PaginationParams, Seller, UpdateCustomerDto,
+    AnalyticsResponse, BatchResponse, CohortMatrix, CohortQuery, CreateCustomerDto, CreateOrderDto,
+    CreateSellerDto, Customer, CustomerRfm, CustomerWithIncludes, DateRange, DeliveryAnalytics,
+    DeliveryAnalyticsQuery, DeliveryEstimate, DeliveryEstimateQuery, DetailQuery, DurationStats,
+    EstimateBasis, FieldSet, FlowQuery, FunnelQuery, GeolocationRecord, Heatmap, HeatmapEntity,
+    HeatmapQuery, LocationSearchQuery, NearbyQuery, NearbySellersResponse, Order, OrderFunnel,
+    OrderItem, OrderPayment, OrderReview, OrderSearchQuery, OrderTimeseries, OrderTimeseriesQuery,
+    OrderWithIncludes, PageRequest, PaginatedResponse, PaginationParams, PaymentAnalytics,
+    PaymentAnalyticsQuery, Product, RFM_SEGMENTS, RefreshReport, ReviewAnalytics,
+    ReviewAnalyticsQuery, ReviewStats, RfmRecomputeResult, RfmSegmentSummary, RfmSegments,
+    SearchQuery, SearchResults, Seller, SellerDeliveryEstimate, SellerRating, SellerRatingQuery,
+    SortSpec, Sparse, StateFlow, TRANSIT_LEVELS, TimeseriesPoint, TimeseriesSeries,
+    TimeseriesSplit, UpdateCustomerDto, ZipCentroid, normalize_lookup_ids, normalize_orders_limit,
+    normalize_state, normalize_zip_prefix, parse_includes, score_rfm,
 };
-use crate::repositories::{CustomerRepository, OrderRepository, SellerRepository};
+use crate::repositories::{
+    ANALYTICS_VIEWS, AnalyticsRepository, CustomerRepository, EstimateRepository, GeoRepository,
+    ORDER_FACTS_VIEW, ORDER_SHIPMENTS_VIEW, OrderRepository, PAYMENT_FACTS_VIEW, REVIEW_FACTS_VIEW,
+    RFM_DATASET, SearchRepository, SellerRepository,
+};
+
+const CUSTOMER_INCLUDES: &[&str] = &["orders"];
+const SELLER_INCLUDES: &[&str] = &[];
+const ORDER_INCLUDES: &[&str] = &["customer"];
 
 #[derive(Clone)]
 pub struct CustomerService {
     repository: Arc<dyn CustomerRepository>,
+    order_repository: Arc<dyn OrderRepository>,
 }
 
 impl CustomerService {
-    pub fn new(repository: Arc<dyn CustomerRepository>) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Arc<dyn CustomerRepository>,
+        order_repository: Arc<dyn OrderRepository>,
+    ) -> Self {
+        Self {
+            repository,
+            order_repository,
+        }
+    }
+
+    /// Attaches the requested relations to a batch of customers with one
+    /// query per relation.
+    async fn with_includes(
+        &self,
+        customers: Vec<Customer>,
+        fields: &FieldSet,
+        includes: &[&str],
+        orders_limit: Option<u32>,
+    ) -> AppResult<Vec<CustomerWithIncludes>> {
+        let mut orders_by_customer: Option<HashMap<String, Vec<Order>>> = None;
+
+        if includes.contains(&"orders") && !customers.is_empty() {
+            let ids: Vec<String> = customers.iter().map(|c| c.customer_id.clone()).collect();
+            let orders = self
+                .order_repository
+                .find_recent_by_customer_ids(&ids, normalize_orders_limit(orders_limit))
+                .await?;
+
+            let mut grouped: HashMap<String, Vec<Order>> = HashMap::new();
+            for order in orders {
+                grouped
+                    .entry(order.customer_id.clone())
+                    .or_default()
+                    .push(order);
+            }
+            orders_by_customer = Some(grouped);
+        }
+
+        Ok(customers
+            .into_iter()
+            .map(|customer| {
+                let orders = orders_by_customer
+                    .as_mut()
+                    .map(|grouped| grouped.remove(&customer.customer_id).unwrap_or_default());
+                CustomerWithIncludes {
+                    customer: fields.apply(customer),
+                    orders,
+                }
+            })
+            .collect())
     }
 
     #[instrument(skip(self))]
@@ -37,11 +108,44 @@ impl CustomerService {
     }
 
     #[instrument(skip(self))]
-    pub async fn get_customer_by_id(&self, id: &str) -> AppResult<Customer> {
-        match self.repository.find_by_id(id).await? {
-            Some(customer) => Ok(customer),
-            None => Err(AppError::NotFound),
-        }
+    pub async fn get_customer_by_id(
+        &self,
+        id: &str,
+        query: &DetailQuery,
+    ) -> AppResult<CustomerWithIncludes> {
+        let fields = FieldSet::parse::<Customer>(query.fields.as_deref())?;
+        let includes = parse_includes(query.include.as_deref(), CUSTOMER_INCLUDES)?;
+
+        let customer = match self.repository.find_by_id(id, &fields).await? {
+            Some(customer) => customer,
+            None => return Err(AppError::NotFound),
+        };
+
+        let mut expanded = self
+            .with_includes(vec![customer], &fields, &includes, query.orders_limit)
+            .await?;
+        Ok(expanded.remove(0))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn lookup_customers(
+        &self,
+        ids: Vec<String>,
+        query: &DetailQuery,
+    ) -> AppResult<BatchResponse<CustomerWithIncludes>> {
+        let ids = normalize_lookup_ids(ids)?;
+        let fields = FieldSet::parse::<Customer>(query.fields.as_deref())?;
+        let includes = parse_includes(query.include.as_deref(), CUSTOMER_INCLUDES)?;
+
+        let customers = self.repository.find_by_ids(&ids, &fields).await?;
+        let batch = BatchResponse::from_rows(&ids, customers, |c| &c.customer_id);
+
+        Ok(BatchResponse {
+            data: self
+                .with_includes(batch.data, &fields, &includes, query.orders_limit)
+                .await?,
+            missing: batch.missing,
+        })
     }
 
     #[instrument(skip(self, dto), fields(customer_id = id))]
@@ -76,20 +180,41 @@ impl CustomerService {
     pub async fn get_customers(
         &self,
         query: LocationSearchQuery,
-    ) -> AppResult<PaginatedResponse<Customer>> {
-        let pagination = query.pagination();
-        let filter = query.filter();
+    ) -> AppResult<PaginatedResponse<CustomerWithIncludes>> {
+        let filter = query.filter::<Customer>()?;
+        let sort = SortSpec::parse::<Customer>(query.sort.as_deref())?;
+        let includes = parse_includes(query.include.as_deref(), CUSTOMER_INCLUDES)?;
+        let fields = FieldSet::parse::<Customer>(query.fields.as_deref())?;
 
-        let (_, _, page, page_size) = pagination.normalize();
+        let page = match query.page_request()? {
+            PageRequest::Offset(pagination) => {
+                let (_, _, page, page_size) = pagination.normalize();
 
-        let (customers, total_records) = self.repository.find_all(&filter, &pagination).await?;
+                let (customers, total_records) = self
+                    .repository
+                    .find_all(&filter, &sort, &fields, &pagination)
+                    .await?;
 
-        Ok(PaginatedResponse::new(
-            customers,
-            total_records,
-            page,
-            page_size,
-        ))
+                PaginatedResponse::new(customers, total_records, page, page_size)
+            }
+            PageRequest::Keyset(keyset) => {
+                let after = keyset.after_values(&sort)?;
+                let (customers, has_more) = self
+                    .repository
+                    .find_page(&filter, &sort, &fields, &keyset, after.as_deref())
+                    .await?;
+
+                PaginatedResponse::from_keyset(customers, has_more, &keyset, &sort)
+            }
+        };
+
+        let data = self
+            .with_includes(page.data, &fields, &includes, query.orders_limit)
+            .await?;
+        Ok(PaginatedResponse {
+            data,
+            meta: page.meta,
+        })
     }
 }
 
@@ -110,71 +235,338 @@ impl SellerService {
     }
 
     #[instrument(skip(self))]
-    pub async fn get_seller_by_id(&self, id: &str) -> AppResult<Seller> {
-        match self.repository.find_by_id(id).await? {
-            Some(seller) => Ok(seller),
+    pub async fn get_seller_by_id(
+        &self,
+        id: &str,
+        query: &DetailQuery,
+    ) -> AppResult<Sparse<Seller>> {
+        let fields = FieldSet::parse::<Seller>(query.fields.as_deref())?;
+        parse_includes(query.include.as_deref(), SELLER_INCLUDES)?;
+
+        match self.repository.find_by_id(id, &fields).await? {
+            Some(seller) => Ok(fields.apply(seller)),
             None => Err(AppError::NotFound),
         }
     }
 
+    #[instrument(skip(self))]
+    pub async fn seller_rating(
+        &self,
+        id: &str,
+        query: &SellerRatingQuery,
+    ) -> AppResult<SellerRating> {
+        query.validate()?;
+        if self
+            .repository
+            .find_by_id(id, &FieldSet::all())
+            .await?
+            .is_none()
+        {
+            return Err(AppError::NotFound);
+        }
+
+        let counts = self.repository.review_score_counts(id).await?;
+        let trend = self
+            .repository
+            .rating_trend(id, query.months() as i32)
+            .await?;
+
+        let mut distribution: BTreeMap<i16, i64> = (1..=5).map(|score| (score, 0)).collect();
+        for count in &counts {
+            distribution.insert(count.review_score, count.reviews);
+        }
+        let review_count: i64 = counts.iter().map(|c| c.reviews).sum();
+        let score_total: i64 = counts
+            .iter()
+            .map(|c| c.review_score as i64 * c.reviews)
+            .sum();
+        let avg_score = (review_count > 0).then(|| score_total as f64 / review_count as f64);
+
+        Ok(SellerRating {
+            seller_id: id.to_string(),
+            review_count,
+            avg_score,
+            distribution,
+            trend,
+        })
+    }
+
+    #[instrument(skip(self))]
+    pub async fn lookup_sellers(
+        &self,
+        ids: Vec<String>,
+        query: &DetailQuery,
+    ) -> AppResult<BatchResponse<Sparse<Seller>>> {
+        let ids = normalize_lookup_ids(ids)?;
+        let fields = FieldSet::parse::<Seller>(query.fields.as_deref())?;
+        parse_includes(query.include.as_deref(), SELLER_INCLUDES)?;
+
+        let sellers = self.repository.find_by_ids(&ids, &fields).await?;
+        let batch = BatchResponse::from_rows(&ids, sellers, |s| &s.seller_id);
+
+        Ok(BatchResponse {
+            data: batch.data.into_iter().map(|s| fields.apply(s)).collect(),
+            missing: batch.missing,
+        })
+    }
+
     #[instrument(skip(self))]
     pub async fn get_sellers(
         &self,
         query: LocationSearchQuery,
-    ) -> AppResult<PaginatedResponse<Seller>> {
-        let pagination = query.pagination();
-        let filter = query.filter();
-        let (_, _, page, page_size) = pagination.normalize();
+    ) -> AppResult<PaginatedResponse<Sparse<Seller>>> {
+        let filter = query.filter::<Seller>()?;
+        let sort = SortSpec::parse::<Seller>(query.sort.as_deref())?;
+        parse_includes(query.include.as_deref(), SELLER_INCLUDES)?;
+        let fields = FieldSet::parse::<Seller>(query.fields.as_deref())?;
+
+        let page = match query.page_request()? {
+            PageRequest::Offset(pagination) => {
+                let (_, _, page, page_size) = pagination.normalize();
 
-        let (sellers, total_records) = self.repository.find_all(&filter, &pagination).await?;
+                let (sellers, total_records) = self
+                    .repository
+                    .find_all(&filter, &sort, &fields, &pagination)
+                    .await?;
+
+                PaginatedResponse::new(sellers, total_records, page, page_size)
+            }
+            PageRequest::Keyset(keyset) => {
+                let after = keyset.after_values(&sort)?;
+                let (sellers, has_more) = self
+                    .repository
+                    .find_page(&filter, &sort, &fields, &keyset, after.as_deref())
+                    .await?;
 
-        Ok(PaginatedResponse::new(
-            sellers,
-            total_records,
-            page,
-            page_size,
-        ))
+                PaginatedResponse::from_keyset(sellers, has_more, &keyset, &sort)
+            }
+        };
+
+        Ok(page.map(|row| fields.apply(row)))
     }
 }
 
+/// Order fieldset, keeping `customer_id` readable when the customer is
+/// embedded.
+fn order_fields(raw: Option<&str>, includes: &[&str]) -> AppResult<FieldSet> {
+    let fields = FieldSet::parse::<Order>(raw)?;
+    Ok(if includes.contains(&"customer") {
+        fields.require("customer_id")
+    } else {
+        fields
+    })
+}
+
 #[derive(Clone)]
 pub struct OrderService {
     repository: Arc<dyn OrderRepository>,
+    customer_repository: Arc<dyn CustomerRepository>,
+    seller_repository: Arc<dyn SellerRepository>,
+    estimate_repository: Arc<dyn EstimateRepository>,
 }
 
 impl OrderService {
-    pub fn new(repository: Arc<dyn OrderRepository>) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Arc<dyn OrderRepository>,
+        customer_repository: Arc<dyn CustomerRepository>,
+        seller_repository: Arc<dyn SellerRepository>,
+        estimate_repository: Arc<dyn EstimateRepository>,
+    ) -> Self {
+        Self {
+            repository,
+            customer_repository,
+            seller_repository,
+            estimate_repository,
+        }
+    }
+
+    /// Attaches the requested relations to a batch of orders with one query
+    /// per relation.
+    async fn with_includes(
+        &self,
+        orders: Vec<Order>,
+        fields: &FieldSet,
+        includes: &[&str],
+    ) -> AppResult<Vec<OrderWithIncludes>> {
+        let mut customers: Option<HashMap<String, Customer>> = None;
+
+        if includes.contains(&"customer") && !orders.is_empty() {
+            let mut ids: Vec<String> = orders.iter().map(|o| o.customer_id.clone()).collect();
+            ids.sort();
+            ids.dedup();
+
+            let found = self
+                .customer_repository
+                .find_by_ids(&ids, &FieldSet::all())
+                .await?;
+            customers = Some(
+                found
+                    .into_iter()
+                    .map(|c| (c.customer_id.clone(), c))
+                    .collect(),
+            );
+        }
+
+        Ok(orders
+            .into_iter()
+            .map(|order| {
+                let customer = customers
+                    .as_ref()
+                    .and_then(|found| found.get(&order.customer_id).cloned());
+                OrderWithIncludes {
+                    order: fields.apply(order),
+                    customer,
+                }
+            })
+            .collect())
     }
 
     #[instrument(skip(self))]
-    pub async fn create_order(&self, dto: CreateOrderDto) -> AppResult<Order> {
+    pub async fn create_order(&self, mut dto: CreateOrderDto) -> AppResult<Order> {
         dto.validate()?;
+
+        // Items are added after the order, so the seller is only known when
+        // the client passes `seller_id`.
+        if dto.order_estimated_delivery_date.is_none() {
+            let customer = self
+                .customer_repository
+                .find_by_id(&dto.customer_id, &FieldSet::all())
+                .await?
+                .ok_or_else(|| {
+                    AppError::BadRequest(format!("Customer '{}' does not exist", dto.customer_id))
+                })?;
+            let seller = match dto.seller_id.as_deref() {
+                Some(seller_id) => Some(
+                    self.seller_repository
+                        .find_by_id(seller_id, &FieldSet::all())
+                        .await?
+                        .ok_or_else(|| {
+                            AppError::BadRequest(format!("Seller '{}' does not exist", seller_id))
+                        })?,
+                ),
+                None => None,
+            };
+            let estimate = estimate_delivery(
+                self.estimate_repository.as_ref(),
+                seller
+                    .as_ref()
+                    .map(|s| (s.seller_id.as_str(), s.seller_state.as_str())),
+                &customer.customer_state,
+                dto.order_purchase_timestamp,
+            )
+            .await?
+            .ok_or_else(|| {
+                AppError::BadRequest(
+                    "order_estimated_delivery_date is required until there is delivery history"
+                        .into(),
+                )
+            })?;
+            dto.order_estimated_delivery_date =
+                estimate.estimated_delivery_date.and_hms_opt(0, 0, 0);
+        }
+
         Ok(self.repository.create(dto).await?)
     }
 
     #[instrument(skip(self))]
-    pub async fn get_order_by_id(&self, id: &str) -> AppResult<Order> {
-        match self.repository.find_by_id(id).await? {
-            Some(order) => Ok(order),
-            None => Err(AppError::NotFound),
-        }
+    pub async fn get_order_by_id(
+        &self,
+        id: &str,
+        query: &DetailQuery,
+    ) -> AppResult<OrderWithIncludes> {
+        let includes = parse_includes(query.include.as_deref(), ORDER_INCLUDES)?;
+        let fields = order_fields(query.fields.as_deref(), &includes)?;
+
+        let order = match self.repository.find_by_id(id, &fields).await? {
+            Some(order) => order,
+            None => return Err(AppError::NotFound),
+        };
+
+        let mut expanded = self.with_includes(vec![order], &fields, &includes).await?;
+        Ok(expanded.remove(0))
     }
 
     #[instrument(skip(self))]
-    pub async fn get_orders(&self, query: OrderSearchQuery) -> AppResult<PaginatedResponse<Order>> {
-        let pagination = query.pagination();
-        let filter = query.filter();
-        let (_, _, page, page_size) = pagination.normalize();
+    pub async fn lookup_orders(
+        &self,
+        ids: Vec<String>,
+        query: &DetailQuery,
+    ) -> AppResult<BatchResponse<OrderWithIncludes>> {
+        let ids = normalize_lookup_ids(ids)?;
+        let includes = parse_includes(query.include.as_deref(), ORDER_INCLUDES)?;
+        let fields = order_fields(query.fields.as_deref(), &includes)?;
 
-        let (orders, total_records) = self.repository.find_all(&filter, &pagination).await?;
+        let orders = self.repository.find_by_ids(&ids, &fields).await?;
+        let batch = BatchResponse::from_rows(&ids, orders, |o| &o.order_id);
 
-        Ok(PaginatedResponse::new(
-            orders,
-            total_records,
-            page,
-            page_size,
-        ))
+        Ok(BatchResponse {
+            data: self.with_includes(batch.data, &fields, &includes).await?,
+            missing: batch.missing,
+        })
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_orders(
+        &self,
+        query: OrderSearchQuery,
+    ) -> AppResult<PaginatedResponse<OrderWithIncludes>> {
+        let filter = query.filter()?;
+        let sort = SortSpec::parse::<Order>(query.sort.as_deref())?;
+        let includes = parse_includes(query.include.as_deref(), ORDER_INCLUDES)?;
+        let fields = order_fields(query.fields.as_deref(), &includes)?;
+
+        let page = match query.page_request()? {
+            PageRequest::Offset(pagination) => {
+                let (_, _, page, page_size) = pagination.normalize();
+
+                let (orders, total_records) = self
+                    .repository
+                    .find_all(&filter, &sort, &fields, &pagination)
+                    .await?;
+
+                PaginatedResponse::new(orders, total_records, page, page_size)
+            }
+            PageRequest::Keyset(keyset) => {
+                let after = keyset.after_values(&sort)?;
+                let (orders, has_more) = self
+                    .repository
+                    .find_page(&filter, &sort, &fields, &keyset, after.as_deref())
+                    .await?;
+
+                PaginatedResponse::from_keyset(orders, has_more, &keyset, &sort)
+            }
+        };
+
+        let data = self.with_includes(page.data, &fields, &includes).await?;
+        Ok(PaginatedResponse {
+            data,
+            meta: page.meta,
+        })
+    }
+
+    #[instrument(skip(self, payments))]
+    pub async fn import_payments(&self, payments: Vec<OrderPayment>) -> AppResult<u64> {
+        Ok(self.repository.upsert_payments(&payments).await?)
+    }
+
+    #[instrument(skip(self, items))]
+    pub async fn import_items(&self, items: Vec<OrderItem>) -> AppResult<u64> {
+        Ok(self.repository.upsert_items(&items).await?)
+    }
+
+    #[instrument(skip(self, products))]
+    pub async fn import_products(&self, products: Vec<Product>) -> AppResult<u64> {
+        Ok(self.repository.upsert_products(&products).await?)
+    }
+
+    /// Reviews with a score outside 1-5 are dropped.
+    #[instrument(skip(self, reviews))]
+    pub async fn import_reviews(&self, reviews: Vec<OrderReview>) -> AppResult<u64> {
+        let reviews: Vec<OrderReview> = reviews
+            .into_iter()
+            .filter(|r| (1..=5).contains(&r.review_score))
+            .collect();
+        Ok(self.repository.upsert_reviews(&reviews).await?)
     }
 
     #[instrument(skip(self))]
@@ -182,13 +574,686 @@ impl OrderService {
         &self,
         customer_id: &str,
         pagination: &PaginationParams,
-    ) -> AppResult<PaginatedResponse<Order>> {
+        fields: Option<&str>,
+    ) -> AppResult<PaginatedResponse<Sparse<Order>>> {
+        let fields = FieldSet::parse::<Order>(fields)?;
         let (_, _, page, page_size) = pagination.normalize();
         let (orders, count) = self
             .repository
-            .find_by_customer_id(customer_id, pagination)
+            .find_by_customer_id(customer_id, &fields, pagination)
             .await?;
 
-        Ok(PaginatedResponse::new(orders, count, page, page_size))
+        Ok(PaginatedResponse::new(orders, count, page, page_size).map(|row| fields.apply(row)))
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchService {
+    repository: Arc<dyn SearchRepository>,
+}
+
+impl SearchService {
+    pub fn new(repository: Arc<dyn SearchRepository>) -> Self {
+        Self { repository }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn search(&self, query: SearchQuery) -> AppResult<SearchResults> {
+        query.validate()?;
+
+        let term = query.q.trim().to_string();
+        let limit = query.limit.unwrap_or(20) as i64;
+        let hits = self.repository.search(&term, limit).await?;
+
+        Ok(SearchResults { query: term, hits })
+    }
+}
+
+/// Rough bounding box of Brazil; geolocation rows outside it are data-entry
+/// errors in the source dataset and would skew the centroids.
+const BRAZIL_LATITUDE_RANGE: std::ops::RangeInclusive<f64> = -34.0..=6.0;
+const BRAZIL_LONGITUDE_RANGE: std::ops::RangeInclusive<f64> = -74.0..=-34.0;
+
+#[derive(Clone)]
+pub struct GeoService {
+    repository: Arc<dyn GeoRepository>,
+    customer_repository: Arc<dyn CustomerRepository>,
+}
+
+impl GeoService {
+    pub fn new(
+        repository: Arc<dyn GeoRepository>,
+        customer_repository: Arc<dyn CustomerRepository>,
+    ) -> Self {
+        Self {
+            repository,
+            customer_repository,
+        }
+    }
+
+    /// Averages the coordinates of every geolocation sample per zip prefix
+    /// and stores the result. The most frequent city spelling wins.
+    #[instrument(skip(self, records))]
+    pub async fn import_centroids(&self, records: Vec<GeolocationRecord>) -> AppResult<u64> {
+        struct Accumulator {
+            latitude: f64,
+            longitude: f64,
+            samples: i32,
+            state: String,
+            cities: HashMap<String, usize>,
+        }
+
+        let mut by_zip: HashMap<String, Accumulator> = HashMap::new();
+        for record in records {
+            if !BRAZIL_LATITUDE_RANGE.contains(&record.geolocation_lat)
+                || !BRAZIL_LONGITUDE_RANGE.contains(&record.geolocation_lng)
+            {
+                continue;
+            }
+            let Ok(zip) = normalize_zip_prefix(&record.geolocation_zip_code_prefix) else {
+                continue;
+            };
+
+            let entry = by_zip.entry(zip).or_insert_with(|| Accumulator {
+                latitude: 0.0,
+                longitude: 0.0,
+                samples: 0,
+                state: record.geolocation_state.clone(),
+                cities: HashMap::new(),
+            });
+            entry.latitude += record.geolocation_lat;
+            entry.longitude += record.geolocation_lng;
+            entry.samples += 1;
+            *entry.cities.entry(record.geolocation_city).or_default() += 1;
+        }
+
+        let centroids: Vec<ZipCentroid> = by_zip
+            .into_iter()
+            .map(|(zip_code_prefix, acc)| {
+                let city = acc
+                    .cities
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                    .map(|(city, _)| city)
+                    .unwrap_or_default();
+
+                ZipCentroid {
+                    zip_code_prefix,
+                    latitude: acc.latitude / acc.samples as f64,
+                    longitude: acc.longitude / acc.samples as f64,
+                    city,
+                    state: acc.state,
+                    sample_count: acc.samples,
+                }
+            })
+            .collect();
+
+        Ok(self.repository.upsert_centroids(&centroids).await?)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn sellers_near_zip(&self, query: NearbyQuery) -> AppResult<NearbySellersResponse> {
+        let zip = query
+            .zip
+            .as_deref()
+            .ok_or_else(|| AppError::BadRequest("Query parameter 'zip' is required".into()))?;
+        let zip = normalize_zip_prefix(zip)?;
+
+        self.sellers_near(&zip, &query).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn sellers_near_customer(
+        &self,
+        customer_id: &str,
+        query: NearbyQuery,
+    ) -> AppResult<NearbySellersResponse> {
+        let fields = FieldSet::all();
+        let customer = self
+            .customer_repository
+            .find_by_id(customer_id, &fields)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let zip = normalize_zip_prefix(&customer.customer_zip_code_prefix)?;
+
+        self.sellers_near(&zip, &query).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn heatmap(&self, query: HeatmapQuery) -> AppResult<Heatmap> {
+        let has_order_filters =
+            query.from.is_some() || query.to.is_some() || query.status.is_some();
+        if query.entity != HeatmapEntity::Orders && has_order_filters {
+            return Err(AppError::BadRequest(
+                "from, to and status only apply to entity=orders".into(),
+            ));
+        }
+
+        let purchased = DateRange::between(query.from, query.to)?;
+        let level = query.level.unwrap_or_default();
+        let rows = self
+            .repository
+            .heatmap(query.entity, level, &purchased, query.status.as_deref())
+            .await?;
+
+        Ok(Heatmap::from_rows(query.entity, level, rows))
+    }
+
+    async fn sellers_near(
+        &self,
+        zip: &str,
+        query: &NearbyQuery,
+    ) -> AppResult<NearbySellersResponse> {
+        query.validate()?;
+
+        let origin = self
+            .repository
+            .find_centroid(zip)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let radius_km = query.radius_km();
+        let data = self
+            .repository
+            .find_sellers_near(&origin, radius_km, query.limit())
+            .await?;
+
+        Ok(NearbySellersResponse {
+            origin,
+            radius_km,
+            data,
+        })
+    }
+}
+
+/// Samples a model level needs before it is preferred over a broader one.
+const MIN_ESTIMATE_SAMPLES: i64 = 20;
+
+const HANDLING_LEVELS: &[&str] = &["seller", "all"];
+
+/// The most specific of `levels` with enough samples, else the broadest one
+/// available.
+fn pick_level(mut stats: Vec<DurationStats>, levels: &[&str]) -> Option<DurationStats> {
+    stats.sort_by_key(|s| {
+        levels
+            .iter()
+            .position(|level| *level == s.level)
+            .unwrap_or(levels.len())
+    });
+    let broadest = stats.last().cloned();
+    stats
+        .into_iter()
+        .find(|s| s.samples >= MIN_ESTIMATE_SAMPLES)
+        .or(broadest)
+}
+
+/// Predicts when an order placed at `purchased_at` arrives. `None` while
+/// there is no delivery history to learn from.
+async fn estimate_delivery(
+    repository: &dyn EstimateRepository,
+    seller: Option<(&str, &str)>,
+    customer_state: &str,
+    purchased_at: chrono::NaiveDateTime,
+) -> AppResult<Option<DeliveryEstimate>> {
+    let (seller_id, seller_state) = seller.unzip();
+    let handling = repository.handling_stats(seller_id).await?;
+    let transit = repository
+        .transit_stats(seller_state, customer_state, purchased_at.month() as i32)
+        .await?;
+
+    let (Some(handling), Some(transit)) = (
+        pick_level(handling, HANDLING_LEVELS),
+        pick_level(transit, TRANSIT_LEVELS),
+    ) else {
+        return Ok(None);
+    };
+
+    let after = |days: f64| {
+        (purchased_at + chrono::Duration::milliseconds((days * 86_400_000.0) as i64)).date()
+    };
+    let expected_days = handling.p50_days + transit.p50_days;
+
+    Ok(Some(DeliveryEstimate {
+        purchased_at,
+        estimated_delivery_date: after(expected_days),
+        earliest: after(handling.p10_days + transit.p10_days),
+        latest: after(handling.p90_days + transit.p90_days),
+        expected_days,
+        handling: EstimateBasis {
+            level: handling.level,
+            samples: handling.samples,
+            median_days: handling.p50_days,
+        },
+        transit: EstimateBasis {
+            level: transit.level,
+            samples: transit.samples,
+            median_days: transit.p50_days,
+        },
+    }))
+}
+
+#[derive(Clone)]
+pub struct EstimateService {
+    repository: Arc<dyn EstimateRepository>,
+    seller_repository: Arc<dyn SellerRepository>,
+    geo_repository: Arc<dyn GeoRepository>,
+}
+
+impl EstimateService {
+    pub fn new(
+        repository: Arc<dyn EstimateRepository>,
+        seller_repository: Arc<dyn SellerRepository>,
+        geo_repository: Arc<dyn GeoRepository>,
+    ) -> Self {
+        Self {
+            repository,
+            seller_repository,
+            geo_repository,
+        }
+    }
+
+    /// Estimates delivery of an order placed now with `seller_id` for a
+    /// customer in `customer_zip`.
+    #[instrument(skip(self))]
+    pub async fn delivery(
+        &self,
+        query: DeliveryEstimateQuery,
+    ) -> AppResult<SellerDeliveryEstimate> {
+        query.validate()?;
+        let zip = normalize_zip_prefix(&query.customer_zip)?;
+
+        let seller = self
+            .seller_repository
+            .find_by_id(&query.seller_id, &FieldSet::all())
+            .await?
+            .ok_or(AppError::NotFound)?;
+        let destination = self
+            .geo_repository
+            .find_centroid(&zip)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+        let purchased_at = chrono::Utc::now().naive_utc().trunc_subsecs(0);
+        let estimate = estimate_delivery(
+            self.repository.as_ref(),
+            Some((&seller.seller_id, &seller.seller_state)),
+            &destination.state,
+            purchased_at,
+        )
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest("Not enough delivery history to estimate a date".into())
+        })?;
+
+        Ok(SellerDeliveryEstimate {
+            seller_id: seller.seller_id,
+            seller_state: seller.seller_state,
+            customer_zip: zip,
+            customer_state: destination.state,
+            estimate,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct AnalyticsService {
+    repository: Arc<dyn AnalyticsRepository>,
+    /// Serializes refreshes between the scheduler and the admin endpoint.
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AnalyticsService {
+    pub fn new(repository: Arc<dyn AnalyticsRepository>) -> Self {
+        Self {
+            repository,
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Wraps `data` with the refresh time of `dataset`.
+    async fn with_refresh_time<T>(
+        &self,
+        dataset: &str,
+        data: T,
+    ) -> AppResult<AnalyticsResponse<T>> {
+        let last_refreshed_at = self
+            .repository
+            .refresh_log()
+            .await?
+            .into_iter()
+            .find(|entry| entry.dataset == dataset)
+            .map(|entry| entry.last_refreshed_at);
+
+        Ok(AnalyticsResponse {
+            last_refreshed_at,
+            data,
+        })
+    }
+
+    /// Rebuilds every materialized view, then the RFM scores that depend on
+    /// them.
+    #[instrument(skip(self))]
+    pub async fn refresh_all(&self) -> AppResult<RefreshReport> {
+        let _guard = self.refresh_lock.lock().await;
+
+        for view in ANALYTICS_VIEWS {
+            self.repository.refresh_view(view).await?;
+        }
+        self.recompute_rfm_scores().await?;
+
+        Ok(RefreshReport {
+            datasets: self.repository.refresh_log().await?,
+        })
+    }
+
+    /// Refreshes the analytics datasets every `every`, starting one period
+    /// after startup.
+    pub async fn run_refresh_schedule(self, every: Duration) {
+        let mut ticker = tokio::time::interval(every);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            match self.refresh_all().await {
+                Ok(report) => info!("Refreshed {} analytics datasets", report.datasets.len()),
+                Err(e) => error!("Scheduled analytics refresh failed: {:?}", e),
+            }
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn delivery(
+        &self,
+        query: DeliveryAnalyticsQuery,
+    ) -> AppResult<AnalyticsResponse<DeliveryAnalytics>> {
+        let purchased = DateRange::between(query.from, query.to)?;
+        let groups = self
+            .repository
+            .delivery_stats(query.group_by, &purchased)
+            .await?;
+
+        let analytics = DeliveryAnalytics {
+            group_by: query.group_by,
+            from: query.from,
+            to: query.to,
+            groups,
+        };
+        self.with_refresh_time(ORDER_FACTS_VIEW, analytics).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn order_timeseries(
+        &self,
+        query: OrderTimeseriesQuery,
+    ) -> AppResult<AnalyticsResponse<OrderTimeseries>> {
+        let state = query.state.as_deref().map(normalize_state);
+        let span = if query.from.is_none() || query.to.is_none() {
+            self.repository.purchase_span(state.as_deref()).await?
+        } else {
+            None
+        };
+        let range = query.range(span)?;
+        let interval = query.interval();
+        let rows = self
+            .repository
+            .order_timeseries(
+                interval,
+                &range,
+                state.as_deref(),
+                query.split_by == Some(TimeseriesSplit::Status),
+            )
+            .await?;
+
+        // Rows arrive ordered by series, then bucket.
+        let mut series: Vec<TimeseriesSeries> = Vec::new();
+        for row in rows {
+            let point = TimeseriesPoint {
+                bucket: row.bucket,
+                order_count: row.order_count,
+            };
+            match series.last_mut() {
+                Some(last) if last.status == row.status => last.points.push(point),
+                _ => series.push(TimeseriesSeries {
+                    status: row.status,
+                    points: vec![point],
+                }),
+            }
+        }
+
+        let timeseries = OrderTimeseries {
+            interval,
+            from: query.from,
+            to: query.to,
+            state,
+            series,
+        };
+        self.with_refresh_time(ORDER_FACTS_VIEW, timeseries).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn cohorts(&self, query: CohortQuery) -> AppResult<AnalyticsResponse<CohortMatrix>> {
+        query.validate()?;
+
+        // Cohorts are whole months, so `from` is widened to its month start.
+        let from = query.from.and_then(|d| d.with_day(1));
+        let first_purchase = DateRange::between(from, query.to)?;
+        let state = query.state.as_deref().map(normalize_state);
+        let periods = query.periods();
+        let cells = self
+            .repository
+            .cohort_cells(&first_purchase, state.as_deref(), periods as i32)
+            .await?;
+
+        let matrix = CohortMatrix::from_cells(cells, state, periods);
+        self.with_refresh_time(ORDER_FACTS_VIEW, matrix).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn funnel(&self, query: FunnelQuery) -> AppResult<AnalyticsResponse<OrderFunnel>> {
+        let purchased = DateRange::between(query.from, query.to)?;
+        let counts = self.repository.funnel_counts(&purchased).await?;
+
+        let funnel = OrderFunnel {
+            from: query.from,
+            to: query.to,
+            stages: counts.stages(),
+        };
+        self.with_refresh_time(ORDER_FACTS_VIEW, funnel).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn state_flows(
+        &self,
+        query: &FlowQuery,
+    ) -> AppResult<AnalyticsResponse<Vec<StateFlow>>> {
+        let purchased = DateRange::between(query.from, query.to)?;
+        let flows = self.repository.state_flows(&purchased).await?;
+        self.with_refresh_time(ORDER_SHIPMENTS_VIEW, flows).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn reviews(
+        &self,
+        query: ReviewAnalyticsQuery,
+    ) -> AppResult<AnalyticsResponse<ReviewAnalytics>> {
+        query.validate()?;
+        let purchased = DateRange::between(query.from, query.to)?;
+
+        let overall = self
+            .repository
+            .review_stats(None, &purchased, 1, 1)
+            .await?
+            .pop()
+            .unwrap_or_else(|| ReviewStats {
+                key: "all".to_string(),
+                review_count: 0,
+                avg_score: None,
+                avg_score_on_time: None,
+                avg_score_late: None,
+                late_rate: None,
+                score_lateness_correlation: None,
+            });
+        let by_lateness = self.repository.lateness_buckets(&purchased).await?;
+        let groups = match query.group_by {
+            Some(group_by) => Some(
+                self.repository
+                    .review_stats(
+                        Some(group_by),
+                        &purchased,
+                        query.min_reviews(),
+                        query.limit(),
+                    )
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let analytics = ReviewAnalytics {
+            group_by: query.group_by,
+            from: query.from,
+            to: query.to,
+            overall,
+            by_lateness,
+            groups,
+        };
+        self.with_refresh_time(REVIEW_FACTS_VIEW, analytics).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn payments(
+        &self,
+        query: PaymentAnalyticsQuery,
+    ) -> AppResult<AnalyticsResponse<PaymentAnalytics>> {
+        let purchased = DateRange::between(query.from, query.to)?;
+        let totals = self.repository.payment_totals(&purchased).await?;
+        let found = self.repository.payment_type_stats(&purchased).await?;
+        let installments = self.repository.installment_counts(&purchased).await?;
+        let by_state = self.repository.ticket_by_state(&purchased).await?;
+
+        let analytics =
+            PaymentAnalytics::new(query.from, query.to, totals, found, installments, by_state);
+        self.with_refresh_time(PAYMENT_FACTS_VIEW, analytics).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn recompute_rfm(&self) -> AppResult<RfmRecomputeResult> {
+        let _guard = self.refresh_lock.lock().await;
+        self.recompute_rfm_scores().await
+    }
+
+    /// Callers must hold `refresh_lock`.
+    async fn recompute_rfm_scores(&self) -> AppResult<RfmRecomputeResult> {
+        let started = Instant::now();
+        // Postgres keeps microseconds; truncate so the reply matches storage.
+        let computed_at = chrono::Utc::now().naive_utc().trunc_subsecs(6);
+        let people = self.repository.rfm_histories().await?;
+        let people_scored = self
+            .repository
+            .replace_rfm(&score_rfm(people, computed_at))
+            .await?;
+        self.repository
+            .record_refresh(RFM_DATASET, started.elapsed().as_millis() as i64)
+            .await?;
+
+        Ok(RfmRecomputeResult {
+            people_scored,
+            computed_at,
+        })
+    }
+
+    #[instrument(skip(self))]
+    pub async fn person_rfm(&self, customer_unique_id: &str) -> AppResult<CustomerRfm> {
+        self.repository
+            .find_rfm(customer_unique_id)
+            .await?
+            .ok_or(AppError::NotFound)
+    }
+
+    /// Every named segment is listed, including empty ones.
+    #[instrument(skip(self))]
+    pub async fn rfm_segments(&self) -> AppResult<AnalyticsResponse<RfmSegments>> {
+        let mut found = self.repository.rfm_segments().await?;
+
+        let segments = RFM_SEGMENTS
+            .iter()
+            .map(|name| match found.iter().position(|s| s.segment == *name) {
+                Some(i) => found.swap_remove(i),
+                None => RfmSegmentSummary {
+                    segment: name.to_string(),
+                    people: 0,
+                    avg_recency_days: None,
+                    avg_frequency: None,
+                    avg_monetary: None,
+                },
+            })
+            .collect();
+
+        self.with_refresh_time(RFM_DATASET, RfmSegments { segments })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(level: &str, samples: i64) -> DurationStats {
+        DurationStats {
+            level: level.to_string(),
+            samples,
+            p10_days: 1.0,
+            p50_days: 2.0,
+            p90_days: 3.0,
+        }
+    }
+
+    fn picked(stats: Vec<DurationStats>, levels: &[&str]) -> Option<String> {
+        pick_level(stats, levels).map(|s| s.level)
+    }
+
+    #[test]
+    fn pick_level_prefers_the_most_specific_level_with_enough_samples() {
+        let transit = vec![
+            stats("all", 90_000),
+            stats("destination", 4_000),
+            stats("route", MIN_ESTIMATE_SAMPLES),
+            stats("route_month", MIN_ESTIMATE_SAMPLES - 1),
+        ];
+        assert_eq!(picked(transit, TRANSIT_LEVELS).as_deref(), Some("route"));
+
+        let handling = vec![stats("all", 90_000), stats("seller", MIN_ESTIMATE_SAMPLES)];
+        assert_eq!(picked(handling, HANDLING_LEVELS).as_deref(), Some("seller"));
+        let handling = vec![
+            stats("seller", MIN_ESTIMATE_SAMPLES - 1),
+            stats("all", 90_000),
+        ];
+        assert_eq!(picked(handling, HANDLING_LEVELS).as_deref(), Some("all"));
+    }
+
+    #[test]
+    fn pick_level_falls_back_to_the_broadest_level_available() {
+        let transit = vec![
+            stats("route", 3),
+            stats("destination_month", 7),
+            stats("destination", 12),
+        ];
+        assert_eq!(
+            picked(transit, TRANSIT_LEVELS).as_deref(),
+            Some("destination")
+        );
+
+        let transit = vec![stats("all", 5), stats("route_month", 2)];
+        assert_eq!(picked(transit, TRANSIT_LEVELS).as_deref(), Some("all"));
+
+        assert_eq!(picked(Vec::new(), TRANSIT_LEVELS), None);
+    }
+
+    #[test]
+    fn pick_level_ranks_unknown_levels_last() {
+        let transit = vec![stats("region", 500), stats("destination", 3)];
+        assert_eq!(picked(transit, TRANSIT_LEVELS).as_deref(), Some("region"));
+
+        let transit = vec![stats("region", 500), stats("route", 30)];
+        assert_eq!(picked(transit, TRANSIT_LEVELS).as_deref(), Some("route"));
     }
 }
@@ -1,8 +1,65 @@
-use crate::services::{CustomerService, OrderService, SellerService};
+use std::sync::Arc;
+
+use sqlx::PgPool;
+
+use crate::repositories::{
+    PgAnalyticsRepository, PgCustomerRepository, PgEstimateRepository, PgGeoRepository,
+    PgOrderRepository, PgSearchRepository, PgSellerRepository,
+};
+use crate::services::{
+    AnalyticsService, CustomerService, EstimateService, GeoService, OrderService, SearchService,
+    SellerService,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub customer_service: CustomerService,
     pub seller_service: SellerService,
     pub order_service: OrderService,
+    pub search_service: SearchService,
+    pub geo_service: GeoService,
+    pub estimate_service: EstimateService,
+    pub analytics_service: AnalyticsService,
+}
+
+impl AppState {
+    /// Wires every service to its Postgres repositories.
+    pub fn new(pool: PgPool) -> Self {
+        let customer_repository = Arc::new(PgCustomerRepository::new(pool.clone()));
+        let seller_repository = Arc::new(PgSellerRepository::new(pool.clone()));
+        let order_repository = Arc::new(PgOrderRepository::new(pool.clone()));
+        let estimate_repository = Arc::new(PgEstimateRepository::new(pool.clone()));
+
+        let customer_service =
+            CustomerService::new(customer_repository.clone(), order_repository.clone());
+        let seller_service = SellerService::new(seller_repository.clone());
+        let order_service = OrderService::new(
+            order_repository,
+            customer_repository.clone(),
+            seller_repository.clone(),
+            estimate_repository.clone(),
+        );
+
+        let search_repository = PgSearchRepository::new(pool.clone());
+        let search_service = SearchService::new(Arc::new(search_repository));
+
+        let geo_repository = Arc::new(PgGeoRepository::new(pool.clone()));
+        let geo_service = GeoService::new(geo_repository.clone(), customer_repository);
+
+        let estimate_service =
+            EstimateService::new(estimate_repository, seller_repository, geo_repository);
+
+        let analytics_repository = PgAnalyticsRepository::new(pool);
+        let analytics_service = AnalyticsService::new(Arc::new(analytics_repository));
+
+        Self {
+            customer_service,
+            seller_service,
+            order_service,
+            search_service,
+            geo_service,
+            estimate_service,
+            analytics_service,
+        }
+    }
 }